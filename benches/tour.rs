@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use libd2d::tour::tour_cost;
+use libd2d::{Coordinate, TourPlannerId};
+use ndarray::Array;
+use ndarray_rand::rand_distr::Uniform;
+use ndarray_rand::RandomExt;
+
+// The loop the mothership visited points of interest with before there were
+// tour planners.
+fn greedy(start: Coordinate, pois: &[Coordinate]) -> Vec<Coordinate> {
+    let mut pois = pois.to_vec();
+    let mut current_position = start;
+    let mut order = Vec::new();
+    while !pois.is_empty() {
+        let mut min = (start, 10000f64);
+        for poi in &pois {
+            let distance = current_position.manhatten_distance(*poi);
+            if distance < min.1 {
+                min = (*poi, distance);
+            }
+        }
+        current_position = min.0;
+        pois.retain(|c| *c != min.0);
+        order.push(min.0);
+    }
+    order
+}
+
+fn scattered(count: usize) -> Vec<Coordinate> {
+    let cells = Array::random((count, 2), Uniform::new(0, 1000));
+    cells.outer_iter().map(|c| Coordinate { x: c[0], y: c[1] }).collect()
+}
+
+fn tours(c: &mut Criterion) {
+    let start = Coordinate { x: -1, y: -1 };
+    for count in [10, 50, 200] {
+        let pois = scattered(count);
+        let mut group = c.benchmark_group(format!("{} points of interest", count));
+
+        println!("greedy: {}", tour_cost(start, &greedy(start, &pois)));
+        group.bench_with_input(BenchmarkId::from_parameter("greedy"), &pois, |b, pois| b.iter(|| greedy(start, pois)));
+        for id in [TourPlannerId::NearestNeighbour, TourPlannerId::TwoOpt, TourPlannerId::OrOpt, TourPlannerId::Exact] {
+            let planner = id.planner();
+            println!("{}: {}", id, planner.plan(start, &pois).cost);
+            group.bench_with_input(BenchmarkId::from_parameter(id), &pois, |b, pois| b.iter(|| planner.plan(start, pois)));
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, tours);
+criterion_main!(benches);
@@ -0,0 +1,309 @@
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::{config, Coordinate, D2dError, TourPlanner};
+
+/// When the mothership sets out for the points of interest found so far,
+/// rather than waiting for more. Whatever the policy, it sets out once the
+/// mission is searched.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DeparturePolicy {
+    // Once the whole mission has been searched.
+    #[default]
+    AfterSearch,
+    // As soon as a point of interest is found.
+    Immediately,
+    // Once this many points of interest are waiting.
+    Batch(usize),
+    // Once the first point of interest found has waited this long.
+    Delay(Duration),
+}
+
+/// Tour of the points of interest that grows as minions find them: new ones
+/// are added to the plan, which is replanned from wherever the mothership is.
+#[derive(Debug)]
+pub struct OnlineTour {
+    pub policy: DeparturePolicy,
+    pub position: Coordinate,
+    pending: Vec<Coordinate>,  // Found but not visited yet.
+    visited: HashSet<Coordinate>,
+    route: VecDeque<Coordinate>, // Planned order of the pending points, while underway.
+    waiting_since: Option<Instant>,
+}
+
+/// Tours handed to other agents, with the points each has yet to act on.
+#[derive(Debug, Default)]
+pub struct AgentTours {
+    remaining: HashMap<PeerId, Vec<Coordinate>>,
+    assigned: bool, // Whether the points of the mission have been split up.
+}
+
+impl DeparturePolicy {
+    /// Policy from `D2D_DEPART`: `after-search` (default), `immediately`,
+    /// `batch:<points>` or `delay:<seconds>`.
+    pub fn from_env() -> Result<Self, D2dError> {
+        config::from_env("D2D_DEPART")
+    }
+
+    fn departs(&self, pending: usize, waited: Duration, searched: bool) -> bool {
+        if pending == 0 {
+            return false;
+        }
+        searched
+            || match *self {
+                DeparturePolicy::AfterSearch => false,
+                DeparturePolicy::Immediately => true,
+                DeparturePolicy::Batch(size) => pending >= size,
+                DeparturePolicy::Delay(delay) => waited >= delay,
+            }
+    }
+}
+
+impl FromStr for DeparturePolicy {
+    type Err = D2dError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || D2dError::InvalidConfig(format!("unknown departure policy {:?}", s));
+        match s.to_ascii_lowercase().split_once(':') {
+            None if s.eq_ignore_ascii_case("after-search") => Ok(DeparturePolicy::AfterSearch),
+            None if s.eq_ignore_ascii_case("immediately") => Ok(DeparturePolicy::Immediately),
+            Some(("batch", size)) => match size.parse() {
+                Ok(size) if size > 0 => Ok(DeparturePolicy::Batch(size)),
+                _ => Err(invalid()),
+            },
+            Some(("delay", seconds)) => match seconds.parse::<f64>() {
+                Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => {
+                    Ok(DeparturePolicy::Delay(Duration::from_secs_f64(seconds)))
+                }
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for DeparturePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeparturePolicy::AfterSearch => write!(f, "after-search"),
+            DeparturePolicy::Immediately => write!(f, "immediately"),
+            DeparturePolicy::Batch(size) => write!(f, "batch:{}", size),
+            DeparturePolicy::Delay(delay) => write!(f, "delay:{}", delay.as_secs_f64()),
+        }
+    }
+}
+
+impl OnlineTour {
+    pub fn new(policy: DeparturePolicy, position: Coordinate) -> Self {
+        OnlineTour {
+            policy,
+            position,
+            pending: Vec::new(),
+            visited: HashSet::new(),
+            route: VecDeque::new(),
+            waiting_since: None,
+        }
+    }
+
+    /// Forgets the points of a previous mission, staying where it is.
+    pub fn reset(&mut self) {
+        *self = OnlineTour::new(self.policy, self.position);
+    }
+
+    /// Adds a point of interest found at `now`, replanning the rest of the
+    /// tour if underway. Points already known are ignored.
+    pub fn add(&mut self, poi: Coordinate, now: Instant, planner: &dyn TourPlanner) -> bool {
+        if self.visited.contains(&poi) || self.pending.contains(&poi) {
+            return false;
+        }
+        self.pending.push(poi);
+        if self.is_underway() {
+            self.replan(planner);
+        } else {
+            self.waiting_since.get_or_insert(now);
+        }
+        true
+    }
+
+    /// The next point to visit at `now`, if the mothership is underway or the
+    /// policy has it set out. `searched` is whether the mission is searched.
+    pub fn next(&mut self, now: Instant, searched: bool, planner: &dyn TourPlanner) -> Option<Coordinate> {
+        if !self.is_underway() {
+            let waited = self.waiting_since.map_or(Duration::ZERO, |since| now.duration_since(since));
+            if !self.policy.departs(self.pending.len(), waited, searched) {
+                return None;
+            }
+            self.waiting_since = None;
+            self.replan(planner);
+        }
+
+        let poi = self.route.pop_front()?;
+        self.pending.retain(|p| *p != poi);
+        self.visited.insert(poi);
+        self.position = poi;
+        Some(poi)
+    }
+
+    pub fn is_underway(&self) -> bool {
+        !self.route.is_empty()
+    }
+
+    /// Whether every point of interest found has been visited.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn visited(&self) -> usize {
+        self.visited.len()
+    }
+
+    /// Takes the points still to be visited out of the tour, to hand them to
+    /// other agents.
+    pub fn take_pending(&mut self) -> Vec<Coordinate> {
+        self.route.clear();
+        self.waiting_since = None;
+        std::mem::take(&mut self.pending)
+    }
+
+    fn replan(&mut self, planner: &dyn TourPlanner) {
+        self.route = planner.plan(self.position, &self.pending).order.into();
+    }
+}
+
+impl AgentTours {
+    pub fn is_assigned(&self) -> bool {
+        self.assigned
+    }
+
+    /// Records the tours handed out, once the points of the mission are
+    /// split up between the agents.
+    pub fn assign(&mut self, tours: impl IntoIterator<Item = (PeerId, Vec<Coordinate>)>) {
+        self.remaining.extend(tours.into_iter().filter(|(_, tour)| !tour.is_empty()));
+        self.assigned = true;
+    }
+
+    /// Records that `agent` acted on `poi`, returning `false` if it was not on
+    /// its tour.
+    pub fn serviced(&mut self, agent: PeerId, poi: Coordinate) -> bool {
+        let tour = match self.remaining.get_mut(&agent) {
+            Some(tour) => tour,
+            None => return false,
+        };
+        let before = tour.len();
+        tour.retain(|p| *p != poi);
+        let serviced = tour.len() < before;
+        if tour.is_empty() {
+            self.remaining.remove(&agent);
+        }
+        serviced
+    }
+
+    /// Takes back what an agent has not acted on, if it dies.
+    pub fn withdraw(&mut self, agent: &PeerId) -> Vec<Coordinate> {
+        self.remaining.remove(agent).unwrap_or_default()
+    }
+
+    /// Whether every agent has finished its tour.
+    pub fn is_complete(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    pub fn remaining(&self, agent: &PeerId) -> usize {
+        self.remaining.get(agent).map_or(0, Vec::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tour::Exact;
+
+    fn at(x: i32, y: i32) -> Coordinate {
+        Coordinate { x, y }
+    }
+
+    #[test]
+    fn waits_for_the_search_by_default() {
+        let now = Instant::now();
+        let mut tour = OnlineTour::new(DeparturePolicy::AfterSearch, at(0, 0));
+        assert!(tour.add(at(5, 5), now, &Exact));
+        assert!(tour.add(at(1, 1), now, &Exact));
+        assert!(!tour.add(at(1, 1), now, &Exact));
+        assert_eq!(tour.next(now, false, &Exact), None);
+
+        assert_eq!(tour.next(now, true, &Exact), Some(at(1, 1)));
+        assert_eq!(tour.next(now, true, &Exact), Some(at(5, 5)));
+        assert_eq!(tour.next(now, true, &Exact), None);
+        assert!(tour.is_idle());
+        // found again by another minion, but already visited
+        assert!(!tour.add(at(5, 5), now, &Exact));
+    }
+
+    #[test]
+    fn replans_while_underway() {
+        let now = Instant::now();
+        let mut tour = OnlineTour::new(DeparturePolicy::Immediately, at(0, 0));
+        tour.add(at(0, 2), now, &Exact);
+        tour.add(at(0, 10), now, &Exact);
+        assert_eq!(tour.next(now, false, &Exact), Some(at(0, 2)));
+
+        // a point between here and the next one is picked up on the way
+        tour.add(at(0, 6), now, &Exact);
+        assert_eq!(tour.next(now, false, &Exact), Some(at(0, 6)));
+        assert_eq!(tour.next(now, false, &Exact), Some(at(0, 10)));
+        assert_eq!((tour.position, tour.visited()), (at(0, 10), 3));
+        assert!(!tour.is_underway() && tour.is_idle());
+    }
+
+    #[test]
+    fn departure_policies() {
+        let now = Instant::now();
+        let mut batch = OnlineTour::new(DeparturePolicy::Batch(2), at(0, 0));
+        batch.add(at(3, 3), now, &Exact);
+        assert_eq!(batch.next(now, false, &Exact), None);
+        batch.add(at(4, 4), now, &Exact);
+        assert_eq!(batch.next(now, false, &Exact), Some(at(3, 3)));
+
+        let mut delay = OnlineTour::new(DeparturePolicy::Delay(Duration::from_secs(5)), at(0, 0));
+        delay.add(at(3, 3), now, &Exact);
+        delay.add(at(4, 4), now + Duration::from_secs(4), &Exact);
+        assert_eq!(delay.next(now + Duration::from_secs(4), false, &Exact), None);
+        assert_eq!(delay.next(now + Duration::from_secs(5), false, &Exact), Some(at(3, 3)));
+    }
+
+    #[test]
+    fn tracks_agent_tours() {
+        let (first, second) = (PeerId::random(), PeerId::random());
+        let mut tours = AgentTours::default();
+        assert!(tours.is_complete() && !tours.is_assigned());
+        tours.assign([(first, vec![at(1, 1), at(2, 2)]), (second, vec![at(9, 9)]), (PeerId::random(), vec![])]);
+        assert!(tours.is_assigned());
+
+        assert!(tours.serviced(first, at(2, 2)));
+        assert!(!tours.serviced(first, at(9, 9)));
+        assert_eq!(tours.remaining(&first), 1);
+        assert!(tours.serviced(second, at(9, 9)));
+        assert_eq!(tours.withdraw(&first), vec![at(1, 1)]);
+        assert!(tours.withdraw(&second).is_empty());
+        assert!(tours.is_complete());
+    }
+
+    #[test]
+    fn policy_names() {
+        config::assert_names(
+            [
+                DeparturePolicy::AfterSearch,
+                DeparturePolicy::Immediately,
+                DeparturePolicy::Batch(4),
+                DeparturePolicy::Delay(Duration::from_millis(2500)),
+            ],
+            "soon",
+        );
+        for invalid in ["batch:0", "batch:x", "delay:-1", "immediately:1"] {
+            assert!(invalid.parse::<DeparturePolicy>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use ndarray::Array2;
+
+/// Assigns every row of `cost` a distinct column so that the total cost is
+/// minimal, using the Hungarian algorithm. Returns the column of each row.
+/// There must be at least as many columns as rows.
+pub fn hungarian(cost: &Array2<f64>) -> Vec<usize> {
+    let (rows, cols) = cost.dim();
+    assert!(rows <= cols, "{} rows cannot be assigned {} columns", rows, cols);
+
+    // Potentials and matching are 1-based, column 0 is the row being added.
+    let mut u = vec![0.0; rows + 1];
+    let mut v = vec![0.0; cols + 1];
+    let mut row_of = vec![0; cols + 1];
+    let mut way = vec![0; cols + 1];
+
+    for row in 1..=rows {
+        row_of[0] = row;
+        let mut col = 0;
+        let mut min_slack = vec![f64::INFINITY; cols + 1];
+        let mut used = vec![false; cols + 1];
+        loop {
+            used[col] = true;
+            let current = row_of[col];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=cols {
+                if used[j] {
+                    continue;
+                }
+                let slack = cost[[current - 1, j - 1]] - u[current] - v[j];
+                if slack < min_slack[j] {
+                    min_slack[j] = slack;
+                    way[j] = col;
+                }
+                if min_slack[j] < delta {
+                    delta = min_slack[j];
+                    next = j;
+                }
+            }
+            for j in 0..=cols {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            col = next;
+            if row_of[col] == 0 {
+                break;
+            }
+        }
+        // flip the augmenting path
+        while col != 0 {
+            let previous = way[col];
+            row_of[col] = row_of[previous];
+            col = previous;
+        }
+    }
+
+    let mut assignment = vec![0; rows];
+    for j in 1..=cols {
+        if row_of[j] != 0 {
+            assignment[row_of[j] - 1] = j - 1;
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn total(cost: &Array2<f64>, assignment: &[usize]) -> f64 {
+        assignment.iter().enumerate().map(|(i, j)| cost[[i, *j]]).sum()
+    }
+
+    // Cheapest assignment found by trying every one.
+    fn brute_force(cost: &Array2<f64>, row: usize, used: &mut Vec<bool>) -> f64 {
+        if row == cost.nrows() {
+            return 0.0;
+        }
+        let mut best = f64::INFINITY;
+        for j in 0..cost.ncols() {
+            if !used[j] {
+                used[j] = true;
+                best = best.min(cost[[row, j]] + brute_force(cost, row + 1, used));
+                used[j] = false;
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn assigns_cheapest() {
+        let cost = ndarray::arr2(&[[4.0, 1.0, 3.0], [2.0, 0.0, 5.0], [3.0, 2.0, 2.0]]);
+        assert_eq!(hungarian(&cost), vec![1, 0, 2]);
+
+        let wide = ndarray::arr2(&[[9.0, 1.0, 9.0, 9.0], [9.0, 2.0, 9.0, 0.5]]);
+        assert_eq!(hungarian(&wide), vec![1, 3]);
+        assert!(hungarian(&Array2::zeros((0, 2))).is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn matches_brute_force(rows in 1usize..6, extra in 0usize..3, seed in prop::collection::vec(0u32..100, 64)) {
+            let cols = rows + extra;
+            let cost = Array2::from_shape_fn((rows, cols), |(i, j)| seed[i * cols + j] as f64);
+            let assignment = hungarian(&cost);
+
+            let mut columns = assignment.clone();
+            columns.sort();
+            columns.dedup();
+            prop_assert_eq!(columns.len(), rows);
+            prop_assert_eq!(total(&cost, &assignment), brute_force(&cost, 0, &mut vec![false; cols]));
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{config, Capabilities, Coordinate, D2dError};
+
+/// How long bids are collected before a round of an auction is closed.
+pub const ROUND: Duration = Duration::from_millis(500);
+
+/// Rounds without a single bid before the auctioneer gives up and assigns
+/// what is left itself.
+pub const MAX_SILENT_ROUNDS: u32 = 6;
+
+/// How the mothership hands out the regions of a mission.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Allocation {
+    // Assigned by the mothership, by travel distance.
+    #[default]
+    Central,
+    // Auctioned off to the minions that bid lowest.
+    Auction,
+}
+
+/// Something put up for auction: an area to search or a point of interest
+/// to act on, which only minions with an actuator bid for.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Lot {
+    Area { origin: Coordinate, rows: usize, cols: usize, cells: usize },
+    Poi(Coordinate),
+}
+
+/// The lots still open in a round of an auction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub auction_id: u64,
+    pub round: u32,
+    pub lots: Vec<(u32, Lot)>,
+}
+
+/// What a bidder would take to finish each lot on top of what it has won
+/// already, in seconds. Lots it cannot take are left out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bid {
+    pub auction_id: u64,
+    pub round: u32,
+    pub costs: Vec<(u32, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Award {
+    pub auction_id: u64,
+    pub lot: u32,
+    pub winner: PeerId,
+}
+
+/// Sells lots one per round to the lowest bid, so that every bidder bids
+/// again knowing what has gone to whom.
+#[derive(Debug)]
+pub struct Auctioneer {
+    pub auction_id: u64,
+    round: u32,
+    lots: BTreeMap<u32, Lot>,
+    bids: HashMap<PeerId, Vec<(u32, f64)>>,
+    silent_rounds: u32,
+}
+
+/// Bids for lots from where a minion is and what it can do, counting the
+/// lots it has won in the same auction.
+#[derive(Debug)]
+pub struct Bidder {
+    pub position: Coordinate,
+    pub capabilities: Capabilities,
+    auction_id: Option<u64>,
+    lots: HashMap<u32, Lot>,
+    busy: f64, // Seconds taken up by the lots won so far.
+    pub won: Vec<u32>,
+}
+
+impl Allocation {
+    /// Allocation selected through the `D2D_ALLOCATION` environment variable,
+    /// central if unset.
+    pub fn from_env() -> Result<Self, D2dError> {
+        config::from_env("D2D_ALLOCATION")
+    }
+}
+
+impl FromStr for Allocation {
+    type Err = D2dError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "central" => Ok(Allocation::Central),
+            "auction" => Ok(Allocation::Auction),
+            _ => Err(D2dError::InvalidConfig(format!("unknown allocation {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Allocation::Central => f.write_str("central"),
+            Allocation::Auction => f.write_str("auction"),
+        }
+    }
+}
+
+impl Lot {
+    /// Where a minion starts working on the lot.
+    pub fn entry(&self) -> Coordinate {
+        match *self {
+            Lot::Area { origin, .. } => origin,
+            Lot::Poi(poi) => poi,
+        }
+    }
+
+    /// Where a minion is once it has finished the lot.
+    pub fn exit(&self) -> Coordinate {
+        match *self {
+            Lot::Area { origin, rows, cols, .. } => Coordinate {
+                x: origin.x + rows as i32 / 2,
+                y: origin.y + cols as i32 / 2,
+            },
+            Lot::Poi(poi) => poi,
+        }
+    }
+}
+
+impl Auctioneer {
+    pub fn new(auction_id: u64, lots: Vec<Lot>) -> Self {
+        Auctioneer {
+            auction_id,
+            round: 0,
+            lots: (0..).zip(lots).collect(),
+            bids: HashMap::new(),
+            silent_rounds: 0,
+        }
+    }
+
+    pub fn announce(&self) -> Announcement {
+        Announcement {
+            auction_id: self.auction_id,
+            round: self.round,
+            lots: self.lots.iter().map(|(id, lot)| (*id, *lot)).collect(),
+        }
+    }
+
+    /// Records a bid, returning `false` for bids on another auction or an
+    /// earlier round.
+    pub fn bid(&mut self, bidder: PeerId, bid: Bid) -> bool {
+        if bid.auction_id != self.auction_id || bid.round != self.round {
+            return false;
+        }
+        let costs = bid.costs.into_iter().filter(|(lot, cost)| self.lots.contains_key(lot) && cost.is_finite());
+        self.bids.insert(bidder, costs.collect());
+        true
+    }
+
+    /// Ends the round, selling the lot with the lowest bid. `None` if nobody
+    /// bid, in which case the round is announced again.
+    pub fn close(&mut self) -> Option<(Award, Lot)> {
+        let bids = std::mem::take(&mut self.bids);
+        let lowest = bids
+            .iter()
+            .flat_map(|(bidder, costs)| costs.iter().map(move |(lot, cost)| (*cost, *lot, *bidder)))
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        let (_, lot, winner) = match lowest {
+            Some(lowest) => lowest,
+            None => {
+                self.silent_rounds += 1;
+                return None;
+            }
+        };
+        let sold = self.lots.remove(&lot)?;
+        self.round += 1;
+        self.silent_rounds = 0;
+        Some((Award { auction_id: self.auction_id, lot, winner }, sold))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.lots.is_empty()
+    }
+
+    /// Whether nobody has bid for so long that the bidders are not coming.
+    pub fn is_abandoned(&self) -> bool {
+        self.silent_rounds >= MAX_SILENT_ROUNDS
+    }
+}
+
+impl Bidder {
+    pub fn new(position: Coordinate, capabilities: Capabilities) -> Self {
+        Bidder { position, capabilities, auction_id: None, lots: HashMap::new(), busy: 0.0, won: Vec::new() }
+    }
+
+    /// Seconds until the lot would be finished, after the lots won already:
+    /// flying there at the minion's speed, then searching it. `None` if the
+    /// minion cannot take it or would run out of endurance.
+    pub fn cost(&self, lot: &Lot) -> Option<f64> {
+        let travel = self.position.euclidean_distance(lot.entry()) / self.capabilities.speed;
+        let work = match lot {
+            Lot::Area { cells, .. } => *cells as f64 / self.capabilities.throughput(),
+            Lot::Poi(_) if self.capabilities.actuator => 0.0,
+            Lot::Poi(_) => return None,
+        };
+        let cost = self.busy + travel + work;
+        (cost <= self.capabilities.endurance).then_some(cost)
+    }
+
+    pub fn is_bidding_in(&self, auction_id: u64) -> bool {
+        self.auction_id == Some(auction_id)
+    }
+
+    /// Bids for every lot announced that the minion can take, starting over
+    /// when a new auction is announced.
+    pub fn bid(&mut self, announcement: &Announcement) -> Bid {
+        if self.auction_id != Some(announcement.auction_id) {
+            self.auction_id = Some(announcement.auction_id);
+            self.busy = 0.0;
+            self.won.clear();
+        }
+        self.lots = announcement.lots.iter().copied().collect();
+        Bid {
+            auction_id: announcement.auction_id,
+            round: announcement.round,
+            costs: self.lots.iter().filter_map(|(id, lot)| self.cost(lot).map(|cost| (*id, cost))).collect(),
+        }
+    }
+
+    /// Takes on a lot this bidder won, to bid for the rest from where it ends.
+    pub fn awarded(&mut self, award: &Award, me: PeerId) -> bool {
+        if award.winner != me || self.auction_id != Some(award.auction_id) {
+            return false;
+        }
+        let lot = match self.lots.remove(&award.lot) {
+            Some(lot) => lot,
+            None => return false,
+        };
+        if let Some(cost) = self.cost(&lot) {
+            self.busy = cost;
+        }
+        self.position = lot.exit();
+        self.won.push(award.lot);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x: i32, y: i32, cells: usize) -> Lot {
+        Lot::Area { origin: Coordinate { x, y }, rows: 4, cols: 4, cells }
+    }
+
+    // Runs an auction to the end with every bidder in the same process.
+    fn run(lots: Vec<Lot>, bidders: &mut [(PeerId, Bidder)]) -> HashMap<u32, PeerId> {
+        let mut auctioneer = Auctioneer::new(7, lots);
+        let mut sold = HashMap::new();
+        while !auctioneer.is_finished() && !auctioneer.is_abandoned() {
+            let announcement = auctioneer.announce();
+            for (peer_id, bidder) in bidders.iter_mut() {
+                assert!(auctioneer.bid(*peer_id, bidder.bid(&announcement)));
+            }
+            if let Some((award, _)) = auctioneer.close() {
+                for (peer_id, bidder) in bidders.iter_mut() {
+                    assert_eq!(bidder.awarded(&award, *peer_id), *peer_id == award.winner);
+                }
+                sold.insert(award.lot, award.winner);
+            }
+        }
+        sold
+    }
+
+    #[test]
+    fn lots_go_to_the_nearest_bidder() {
+        let (near, far) = (PeerId::random(), PeerId::random());
+        let mut bidders = [
+            (near, Bidder::new(Coordinate { x: 0, y: 0 }, Capabilities::default())),
+            (far, Bidder::new(Coordinate { x: 40, y: 40 }, Capabilities::default())),
+        ];
+        let sold = run(vec![area(0, 4, 16), area(36, 40, 16)], &mut bidders);
+        assert_eq!(sold, HashMap::from([(0, near), (1, far)]));
+        assert_eq!(bidders[0].1.won, vec![0]);
+    }
+
+    #[test]
+    fn work_is_shared_out() {
+        // from the same place, a fast minion takes twice the work of a slow one
+        let (slow, fast) = (PeerId::random(), PeerId::random());
+        let start = Coordinate { x: 0, y: 0 };
+        let mut bidders = [
+            (slow, Bidder::new(start, Capabilities::default())),
+            (fast, Bidder::new(start, Capabilities { speed: 20.0, ..Capabilities::default() })),
+        ];
+        let lots = (0..6).map(|i| area(0, 0, 100 + i)).collect();
+        let sold = run(lots, &mut bidders);
+        assert_eq!(sold.values().filter(|w| **w == slow).count(), 2);
+        assert_eq!(sold.values().filter(|w| **w == fast).count(), 4);
+    }
+
+    #[test]
+    fn bidders_only_take_what_they_can() {
+        let (searcher, actuator) = (PeerId::random(), PeerId::random());
+        let tired = Capabilities { endurance: 5.0, ..Capabilities::default() };
+        let mut bidders = [
+            (searcher, Bidder::new(Coordinate { x: 0, y: 0 }, tired)),
+            (actuator, Bidder::new(Coordinate { x: 9, y: 9 }, Capabilities { actuator: true, ..Capabilities::default() })),
+        ];
+        // only the actuator can act, and the area is too much for the tired searcher
+        let sold = run(vec![Lot::Poi(Coordinate { x: 1, y: 1 }), area(0, 0, 100)], &mut bidders);
+        assert_eq!(sold, HashMap::from([(0, actuator), (1, actuator)]));
+
+        // nobody can take it, so the auction is abandoned
+        let mut bidders = [(searcher, Bidder::new(Coordinate { x: 0, y: 0 }, tired))];
+        assert!(run(vec![area(0, 0, 100)], &mut bidders).is_empty());
+    }
+
+    #[test]
+    fn stale_bids_are_ignored() {
+        let bidder = PeerId::random();
+        let mut auctioneer = Auctioneer::new(1, vec![area(0, 0, 1), area(5, 5, 1)]);
+        assert!(!auctioneer.bid(bidder, Bid { auction_id: 2, round: 0, costs: vec![(0, 1.0)] }));
+        assert!(auctioneer.bid(bidder, Bid { auction_id: 1, round: 0, costs: vec![(0, 3.0), (1, 2.0), (9, 0.0)] }));
+        assert_eq!(auctioneer.close().map(|(award, lot)| (award.lot, lot)), Some((1, area(5, 5, 1))));
+        assert!(!auctioneer.bid(bidder, Bid { auction_id: 1, round: 0, costs: vec![(0, 1.0)] }));
+        assert_eq!(auctioneer.close(), None);
+        assert_eq!(auctioneer.announce().lots, vec![(0, area(0, 0, 1))]);
+    }
+
+    #[test]
+    fn allocation_names() {
+        config::assert_names([Allocation::Central, Allocation::Auction], "lottery");
+    }
+}
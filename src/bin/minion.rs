@@ -1,11 +1,22 @@
 use futures::{prelude::*, select};
 use libp2p::gossipsub::{GossipsubEvent, IdentTopic as Topic, MessageAuthenticity, ValidationMode};
+use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
 use libp2p::{gossipsub, identity, swarm::SwarmEvent, Multiaddr, PeerId};
+use ndarray::Array2;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use libd2d::{Coordinate, DelegateTaskMessage, MinionState, MinionStream};
+use libd2d::auction::Bidder;
+use libd2d::consensus::{self, mission_id, Consensus, Coordination, Member, PeerMission};
+use libd2d::delivery::seed_id;
+use libd2d::{
+    liveness::HEARTBEAT_INTERVAL, search_order, ticks, Capabilities, CodecId, Coordinate, D2dBehaviour,
+    D2dEvent, D2dMessage, DelegateTaskMessage, Delivery, FailureDetector, Messenger, MinionEvent, MinionHeartbeat,
+    MinionState, MinionStatus, MinionStream, Outbox, PartitionerId, PatternId, PeerVersions, Role, TourPlannerId, TransitMap,
+    PROTOCOL_VERSION,
+};
 
 #[async_std::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -17,6 +28,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
         local_position: Coordinate { x: 0, y: 0 },
         area_exhausted: false,
         poi: false,
+        transit: VecDeque::new(),
+        in_transit: false,
         mission_area: None,
         waker: None,
     }));
@@ -26,13 +39,31 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let local_peer_id = PeerId::from(local_key.public());
     println!("Local peer id: {:?}", local_peer_id);
 
+    // Select the wire codec, JSON unless overridden with D2D_CODEC
+    let codec = CodecId::from_env()?;
+    let mut messenger = Messenger::new(local_peer_id, codec.codec());
+    println!("Encoding messages with {}", messenger.codec().id());
+
+    // Advertise what this minion can do, the defaults unless overridden with D2D_SPEED, D2D_SENSOR_FOOTPRINT and D2D_ENDURANCE
+    let capabilities = Capabilities::from_env()?;
+    println!("Searching {} cells per second for {} seconds", capabilities.throughput(), capabilities.endurance);
+
+    // Take orders from the mothership, or agree on missions with the other minions with D2D_COORDINATION=peer
+    let coordination = Coordination::from_env()?;
+    println!("Coordinated by {}", coordination);
+
     // Set up an encrypted TCP Transport over the Mplex and Yamux protocols
     let transport = libp2p::development_transport(local_key.clone()).await?;
 
-    let topic_delegate_task = Topic::new("delegate_task");
+    let topic_handshake = Topic::new("handshake");
+    let topic_chunk = Topic::new("chunk");
     let topic_poi = Topic::new("poi");
-    let topic_task_complete = Topic::new("task_complete");
     let topic_report = Topic::new("reporting");
+    let topic_acting = Topic::new("acting");
+    let topic_auction = Topic::new("auction");
+    let topic_new_mission = Topic::new("new_mission");
+    let topic_heartbeat = Topic::new("heartbeat");
+    let topic_consensus = Topic::new("consensus");
 
     // Create a Swarm to manage peers and events
     let mut swarm = {
@@ -47,86 +78,388 @@ async fn main() -> Result<(), Box<dyn Error>> {
             gossipsub::Gossipsub::new(MessageAuthenticity::Signed(local_key), gossipsub_config)
                 .expect("Correct configuration");
 
-        gossipsub.subscribe(&topic_delegate_task).unwrap();
+        gossipsub.subscribe(&topic_handshake).unwrap();
+        gossipsub.subscribe(&topic_chunk).unwrap();
         gossipsub.subscribe(&topic_poi).unwrap();
-        gossipsub.subscribe(&topic_task_complete).unwrap();
         gossipsub.subscribe(&topic_report).unwrap();
+        gossipsub.subscribe(&topic_acting).unwrap();
+        gossipsub.subscribe(&topic_auction).unwrap();
+        gossipsub.subscribe(&topic_new_mission).unwrap();
+        if coordination == Coordination::Peer {
+            gossipsub.subscribe(&topic_heartbeat).unwrap();
+            gossipsub.subscribe(&topic_consensus).unwrap();
+        }
 
-        libp2p::Swarm::new(transport, gossipsub, local_peer_id)
+        libp2p::Swarm::new(transport, D2dBehaviour::new(gossipsub, codec), local_peer_id)
     };
 
-    let address: Multiaddr = "/ip4/127.0.0.1/tcp/60740".parse().unwrap();
-    match swarm.dial(address.clone()) {
-        Ok(_) => println!("Dialed {:?}", address),
-        Err(e) => println!("Dial {:?} failed: {:?}", address, e),
-    };
+    if coordination == Coordination::Mothership {
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/60740".parse().unwrap();
+        match swarm.dial(address.clone()) {
+            Ok(_) => println!("Dialed {:?}", address),
+            Err(e) => println!("Dial {:?} failed: {:?}", address, e),
+        };
+    }
 
-    let mut listen_success: Option<()> = None;
-    for addr in [
+    let minion_addresses = [
         "/ip4/127.0.0.1/tcp/60741",
         "/ip4/127.0.0.1/tcp/60742",
         "/ip4/127.0.0.1/tcp/60743",
         "/ip4/127.0.0.1/tcp/60744",
         "/ip4/127.0.0.1/tcp/60745",
         "/ip4/127.0.0.1/tcp/60746",
-    ] {
-        match swarm.listen_on(addr.parse().unwrap()) {
-            Ok(_) => {
-                listen_success = Some(());
-                break;
-            }
-            Err(_) => {}
+    ];
+    let listening = match minion_addresses.iter().position(|addr| swarm.listen_on(addr.parse().unwrap()).is_ok()) {
+        Some(listening) => listening,
+        None => panic!("No available addresses!"),
+    };
+
+    // Without a mothership to connect them, minions connect to the ones started before them
+    if coordination == Coordination::Peer {
+        for addr in &minion_addresses[..listening] {
+            let address: Multiaddr = addr.parse().unwrap();
+            match swarm.dial(address.clone()) {
+                Ok(_) => println!("Dialed {:?}", address),
+                Err(e) => println!("Dial {:?} failed: {:?}", address, e),
+            };
         }
     }
 
-    if let None = listen_success {
-        panic!("No available addresses!");
-    };
-
     let thread_shared_state = Arc::clone(&state);
-    let poi_stream = MinionStream::new(thread_shared_state);
-    let mut poi_stream = poi_stream
-        .chain(futures::stream::iter(std::iter::from_fn(|| None)))
-        .fuse();
+    let poi_stream = MinionStream::new(thread_shared_state, capabilities.step_interval(), capabilities.search_interval());
+    let mut poi_stream = poi_stream.fuse();
+
+    let mut peer_versions = PeerVersions::default();
+
+    // The task being searched and the mothership that assigned it
+    let mut current_task: Option<(u64, PeerId)> = None;
+    // Accepted tasks waiting for the current one to finish
+    let mut queued_tasks: VecDeque<QueuedTask> = VecDeque::new();
+    // Where cells were released for each steal request, to answer retransmissions alike
+    let mut released_cells = HashMap::new();
+    // Every task ever accepted or cancelled, so that retransmitted assignments are not searched twice
+    let mut accepted_tasks = HashSet::new();
+    let mut cancelled_tasks = HashSet::new();
+    // Completions and requests for work awaiting an ack from the mothership
+    let mut requests = Outbox::default();
+    // Points of interest the mothership handed to this minion to act on
+    let mut tour: VecDeque<Coordinate> = VecDeque::new();
+    // Bids for regions the mothership auctions, from where the lots already won leave the minion
+    let mut bidder = Bidder::new(Coordinate { x: 0, y: 0 }, capabilities);
+
+    // Without a mothership, the mission agreed with the other minions, how they
+    // split it, who of them failed and the points of interest left to act on
+    let mut consensus: Option<Consensus> = None;
+    let mut peer_mission: Option<PeerMission> = None;
+    let partitioner = PartitionerId::from_env()?.partitioner();
+    let tour_planner = TourPlannerId::from_env()?.planner();
+    let mut failures = FailureDetector::from_env()?;
+    let mut found: Vec<Coordinate> = Vec::new();
+    let mut new_mission: Option<(Array2<u32>, PatternId)> = None;
+    // No-go cells of the whole mission, flown around on the way to any task
+    let mut mission_map: Option<TransitMap> = None;
+
+    let mut chunk_ticks = ticks(Duration::from_secs(1));
+    let mut retry_ticks = ticks(Duration::from_secs(1));
+    let mut heartbeat_ticks = ticks(HEARTBEAT_INTERVAL);
+    let mut acting_ticks = ticks(Duration::from_millis(100));
+    let mut consensus_ticks = ticks(consensus::ROUND);
 
     loop {
         select! {
+            _ = chunk_ticks.select_next_some() => {
+                if let Err(e) = messenger.request_missing(&mut swarm.behaviour_mut().gossipsub) {
+                    println!("Publish error: {}", e);
+                }
+            },
+            _ = retry_ticks.select_next_some() => {
+                for delivery in requests.due(Instant::now()) {
+                    match delivery {
+                        Delivery::Resend { id, peer_id, message } => {
+                            println!("Resending request {} to {}", id, peer_id);
+                            swarm.behaviour_mut().tasks.send_request(&peer_id, message);
+                        },
+                        Delivery::GaveUp { id, .. } => {
+                            println!("Giving up on request {}", id);
+                        },
+                    }
+                }
+            },
+            _ = heartbeat_ticks.select_next_some() => {
+                let state = state.lock().unwrap();
+                let heartbeat = MinionHeartbeat {
+                    position: state.local_position + state.global_position,
+                    poi: state.poi,
+                };
+                drop(state);
+                // publishing fails until the mothership has subscribed, which is expected
+                let _ = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &D2dMessage::Heartbeat(heartbeat));
+            },
+            _ = acting_ticks.select_next_some() => {
+                // points of interest wait while a task is searched, which moves the minion
+                let next = if current_task.is_none() { tour.pop_front() } else { None };
+                if let Some(poi) = next {
+                    let mut state = state.lock().unwrap();
+                    state.global_position = poi;
+                    state.local_position = Coordinate { x: 0, y: 0 };
+                    drop(state);
+                    println!("Acting on {:?}", poi);
+                    if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &D2dMessage::ReportMothership(poi)) {
+                        println!("Publish error: {}", e);
+                    }
+                }
+
+                // without a mothership, minions act on what they found once their share is searched
+                if let Some(mission) = peer_mission.as_mut() {
+                    let idle = current_task.is_none() && queued_tasks.is_empty() && tour.is_empty();
+                    if idle && !found.is_empty() {
+                        let position = state.lock().unwrap().global_position;
+                        tour = tour_planner.plan(position, &found).order.into();
+                        found.clear();
+                        println!("Acting on {} points of interest found", tour.len());
+                    } else if idle && !mission.has_finished(&local_peer_id) {
+                        println!("Finished share of mission {:x}", mission.mission());
+                        let dead = mission.dead();
+                        mission.finish(local_peer_id, dead.clone());
+                        let done = D2dMessage::Done { mission: mission.mission(), dead };
+                        if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &done) {
+                            println!("Publish error: {}", e);
+                        }
+                    }
+
+                    if mission.is_complete() {
+                        println!("Mission {:x} complete", mission.mission());
+                        if mission.reporter() == Some(local_peer_id) {
+                            if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &D2dMessage::MissionComplete) {
+                                println!("Publish error: {}", e);
+                            }
+                        }
+                        peer_mission = None;
+                        consensus = None;
+                        new_mission = None;
+                    }
+                }
+            },
+            _ = consensus_ticks.select_next_some() => {
+                let now = Instant::now();
+                // members that stop sending heartbeats leave their share to the others
+                for (peer_id, status) in failures.check(now) {
+                    let mission = match peer_mission.as_mut() {
+                        Some(mission) if status == MinionStatus::Dead => mission,
+                        _ => continue,
+                    };
+                    if mission.fail(peer_id) {
+                        println!("{} failed, taking over part of its share", peer_id);
+                        let areas = mission.take(local_peer_id, partitioner.as_ref());
+                        queue_share(&state, &mut queued_tasks, local_peer_id, mission.pattern, areas);
+                        if current_task.is_none() {
+                            current_task = start_next_task(&state, &mut queued_tasks, mission_map.as_ref());
+                        }
+                    }
+                }
+
+                if let Some(agreeing) = consensus.as_mut() {
+                    agreeing.tick(now);
+                    if agreeing.is_excluded() {
+                        println!("Left out of mission {:x}", agreeing.mission);
+                        // the others split the whole mission between them, what this minion took on included
+                        if peer_mission.take().is_some() {
+                            queued_tasks.clear();
+                            found.clear();
+                        }
+                        consensus = None;
+                        continue;
+                    }
+                    // keep committing, so that the others can agree too
+                    if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &D2dMessage::Commit(agreeing.commit())) {
+                        println!("Publish error: {}", e);
+                    }
+                    // members that missed a round can still leave some out after this minion agreed
+                    let agreed = agreeing
+                        .agreement()
+                        .filter(|agreement| peer_mission.as_ref().is_none_or(|m| m.agreement != *agreement))
+                        .and_then(|agreement| new_mission.clone().map(|mission| (agreement, mission)));
+                    if let Some((agreement, (area, pattern))) = agreed {
+                        println!("Agreed on mission {:x} with {} minions", agreement.mission, agreement.members.len());
+                        // the share agreed before is split anew
+                        if peer_mission.is_some() {
+                            queued_tasks.clear();
+                        }
+                        // members that never send a heartbeat are noticed too
+                        for member in agreement.members.iter().filter(|m| m.peer_id != local_peer_id) {
+                            failures.heartbeat(member.peer_id, now);
+                        }
+                        let mut mission = PeerMission::new(agreement, area, pattern);
+                        let areas = mission.take(local_peer_id, partitioner.as_ref());
+                        queue_share(&state, &mut queued_tasks, local_peer_id, pattern, areas);
+                        if current_task.is_none() {
+                            current_task = start_next_task(&state, &mut queued_tasks, mission_map.as_ref());
+                        }
+                        peer_mission = Some(mission);
+                    }
+                }
+            },
             event = swarm.select_next_some() => match event {
-                SwarmEvent::Behaviour(GossipsubEvent::Message {
+                SwarmEvent::Behaviour(D2dEvent::Gossipsub(GossipsubEvent::Subscribed {
+                    peer_id: _,
+                    topic,
+                })) if topic == topic_handshake.hash() => {
+                    let hello = D2dMessage::Hello { version: PROTOCOL_VERSION, role: Role::Minion };
+                    for message in [hello, D2dMessage::Capabilities(capabilities)] {
+                        if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &message) {
+                            println!("Publish error: {}", e);
+                        }
+                    }
+                },
+                SwarmEvent::Behaviour(D2dEvent::Gossipsub(GossipsubEvent::Message {
                     propagation_source: _peer_id,
                     message_id: _id,
                     message,
-                }) => {
-                    match message.topic.as_str() {
+                })) => {
+                    match messenger.receive(&mut swarm.behaviour_mut().gossipsub, &message) {
 
-                        "delegate_task" => {
+                        Ok(Some(D2dMessage::Hello { version, role })) => {
+                            if let Some(peer_id) = message.source {
+                                let compatibility = peer_versions.record(peer_id, version);
+                                println!("{:?} {} speaks {}: {:?}", role, peer_id, version, compatibility);
+                            }
+                        },
 
-                            let task: DelegateTaskMessage =
-                                serde_json::from_str(
-                                    &String::from_utf8_lossy(&message.data)
-                                ).unwrap();
+                        Ok(Some(D2dMessage::AgentTour { agent, tour: pois })) if agent == local_peer_id => {
+                            if capabilities.actuator {
+                                println!("Acting on {} points of interest", pois.len());
+                                tour = pois.into();
+                            } else {
+                                println!("Cannot act on points of interest");
+                            }
+                        },
 
-                            if task.peer_id == local_peer_id {
-                                { // update state
-                                    let mut state = state.lock().unwrap();
+                        Ok(Some(D2dMessage::NewMission { area, pattern })) => {
+                            mission_map = Some(TransitMap::new(Coordinate { x: 0, y: 0 }, &area));
+                            let mission = mission_id(&area, pattern);
+                            if coordination == Coordination::Peer && consensus.as_ref().is_none_or(|c| c.mission != mission) {
+                                let position = {
+                                    let state = state.lock().unwrap();
+                                    state.global_position + state.local_position
+                                };
+                                println!("Agreeing on mission {:x} with the other minions", mission);
+                                let agreeing = Consensus::new(mission, Member { peer_id: local_peer_id, position, capabilities }, Instant::now());
+                                if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &D2dMessage::Commit(agreeing.commit())) {
+                                    println!("Publish error: {}", e);
+                                }
+                                consensus = Some(agreeing);
+                                new_mission = Some((area, pattern));
+                                peer_mission = None;
+                                found.clear();
+                            }
+                        },
 
-                                    let indexed = task.area.indexed_iter();
-                                    let collected = indexed.collect::<Vec<_>>();
-                                    let owned_iter = collected.into_iter();
-                                    let x = owned_iter.map(|((i, j), k)| ((i as i32, j as i32), *k));
-                                    state.mission_area = Some(x.collect::<Vec<_>>().into_iter());
+                        Ok(Some(D2dMessage::Commit(commit))) => {
+                            let source = message.source.filter(|p| peer_versions.is_compatible(p));
+                            if let (Some(agreeing), Some(peer_id)) = (consensus.as_mut(), source) {
+                                agreeing.receive(peer_id, commit, Instant::now());
+                            }
+                        },
 
-                                    state.global_position = task.global_coordinates;
+                        Ok(Some(D2dMessage::Done { mission, dead })) => {
+                            if let (Some(agreed), Some(peer_id)) = (peer_mission.as_mut().filter(|m| m.mission() == mission), message.source) {
+                                agreed.finish(peer_id, dead);
+                            }
+                        },
 
-                                    state.ready = true;
-                                }
+                        Ok(Some(D2dMessage::Heartbeat(_))) => {
+                            if let Some(peer_id) = message.source {
+                                failures.heartbeat(peer_id, Instant::now());
+                            }
+                        },
+
+                        Ok(Some(D2dMessage::Announce(announcement))) => {
+                            if !bidder.is_bidding_in(announcement.auction_id) {
+                                let state = state.lock().unwrap();
+                                bidder.position = state.global_position + state.local_position;
+                            }
+                            let bid = D2dMessage::Bid(bidder.bid(&announcement));
+                            if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &bid) {
+                                println!("Publish error: {}", e);
                             };
+                        },
 
+                        Ok(Some(D2dMessage::Award(award))) => {
+                            if bidder.awarded(&award, local_peer_id) {
+                                println!("Won lot {} of auction {}", award.lot, award.auction_id);
+                            }
                         },
 
-                        _ => {}
+                        Ok(_) => {}
+                        Err(e) => println!("Dropping message: {}", e),
                     }
                 },
+                SwarmEvent::Behaviour(D2dEvent::Tasks(RequestResponseEvent::Message {
+                    peer,
+                    message: RequestResponseMessage::Request { request, channel, .. },
+                })) => {
+                    let response = match request {
+                        D2dMessage::DelegateTask(task) if task.peer_id != local_peer_id => {
+                            D2dMessage::TaskRejected {
+                                task_id: task.task_id,
+                                reason: "addressed to another minion".to_string(),
+                            }
+                        },
+                        // a retransmission of a task already accepted
+                        D2dMessage::DelegateTask(task) if accepted_tasks.contains(&task.task_id) => {
+                            D2dMessage::TaskAccepted { task_id: task.task_id }
+                        },
+                        D2dMessage::DelegateTask(task) => {
+                            let task_id = task.task_id;
+                            accepted_tasks.insert(task_id);
+                            queued_tasks.push_back(QueuedTask::new(task, peer));
+                            if current_task.is_none() {
+                                current_task = start_next_task(&state, &mut queued_tasks, mission_map.as_ref());
+                            }
+                            println!("Accepted task {} from {}", task_id, peer);
+                            D2dMessage::TaskAccepted { task_id }
+                        },
+                        D2dMessage::CancelTask { task_id } => {
+                            accepted_tasks.insert(task_id);
+                            cancelled_tasks.insert(task_id);
+                            queued_tasks.retain(|task| task.task_id != task_id);
+                            if current_task.is_some_and(|(id, _)| id == task_id) {
+                                // stop after the current cell, the task is not reported as complete
+                                let mut state = state.lock().unwrap();
+                                state.transit.clear();
+                                state.mission_area = Some(Vec::new().into_iter());
+                            }
+                            println!("Cancelled task {}", task_id);
+                            D2dMessage::Ack { id: task_id }
+                        },
+                        D2dMessage::StealWork { id, task_id } => {
+                            let from = *released_cells
+                                .entry(id)
+                                .or_insert_with(|| release_half(&state, current_task, &mut queued_tasks, task_id));
+                            println!("Released cells of task {} from {:?}", task_id, from);
+                            D2dMessage::WorkReleased { id, task_id, from }
+                        },
+                        _ => D2dMessage::TaskRejected { task_id: 0, reason: "unexpected request".to_string() },
+                    };
+
+                    if swarm.behaviour_mut().tasks.send_response(channel, response).is_err() {
+                        println!("Response to {} failed: connection closed", peer);
+                    }
+                },
+                SwarmEvent::Behaviour(D2dEvent::Tasks(RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Response { response: D2dMessage::Ack { id }, .. },
+                    ..
+                })) => {
+                    requests.ack(id);
+                },
+                SwarmEvent::Behaviour(D2dEvent::Tasks(RequestResponseEvent::OutboundFailure {
+                    peer,
+                    error,
+                    ..
+                })) => {
+                    // the outbox resends the request once it is due
+                    println!("Request to {} failed: {:?}", peer, error);
+                },
                 SwarmEvent::NewListenAddr { address, .. } => {
                     println!("Listening on {:?}", address);
                 }
@@ -135,40 +468,55 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
         x = poi_stream.next() => {
             match x {
-                Some(x) => {
+                Some(MinionEvent::Searched(x)) => {
                     let state = state.lock().unwrap();
                     let adjusted_poi = x.position + state.global_position;
-                    let adjusted_coor =(adjusted_poi, x.poi);
                     drop(state);
-                    let poi_serialized = serde_json::to_string(&adjusted_poi).unwrap();
-                    let coor_serialized = serde_json::to_string(&adjusted_coor).unwrap();
 
-                    println!{"Searching {:?}. POI: {}", adjusted_coor.0, adjusted_coor.1};
+                    println!{"Searching {:?}. POI: {}", adjusted_poi, x.poi};
 
+                    if x.poi && peer_mission.is_some() && !found.contains(&adjusted_poi) {
+                        found.push(adjusted_poi);
+                    }
                     if x.poi { // Publish to poi if current locaiton is a poi.
-                        if let Err(e) = swarm
-                            .behaviour_mut()
-                            .publish(topic_poi.clone(), poi_serialized.as_bytes())
-                        {
-                            println!("Publish error: {:?}", e);
+                        if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &D2dMessage::Poi(adjusted_poi)) {
+                            println!("Publish error: {}", e);
                         }
                     };
-                    if let Err(e) = swarm
-                        .behaviour_mut()
-                        .publish(topic_report.clone(), coor_serialized.as_bytes())
-                    {
-                        println!("Publish error: {:?}", e);
+                    let report = D2dMessage::Report { position: adjusted_poi, poi: x.poi };
+                    if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &report) {
+                        println!("Publish error: {}", e);
                     };
                 },
-                None => {
+                Some(MinionEvent::Moved(position)) => {
+                    let global_position = state.lock().unwrap().global_position;
+                    println!("Flying over {:?}", position + global_position);
+                    let heartbeat = MinionHeartbeat { position: position + global_position, poi: false };
+                    if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &D2dMessage::Heartbeat(heartbeat)) {
+                        println!("Publish error: {}", e);
+                    }
+                },
+                Some(MinionEvent::AreaExhausted) => {
                     println!("Search complete");
-                    if let Err(e) = swarm
-                        .behaviour_mut()
-                        .publish(topic_task_complete.clone(), "".as_bytes())
-                    {
-                        println!("Publish error: {:?}", e);
+                    let finished = current_task.take();
+                    // tasks a minion took on itself are not reported to anyone
+                    let finished = finished.filter(|(_, mothership)| *mothership != local_peer_id);
+                    if let Some((task_id, mothership)) = finished.filter(|(id, _)| !cancelled_tasks.contains(id)) {
+                        let complete = D2dMessage::TaskComplete { task_id };
+                        swarm.behaviour_mut().tasks.send_request(&mothership, complete.clone());
+                        requests.insert(task_id, mothership, complete, Instant::now());
                     }
-                }
+                    current_task = start_next_task(&state, &mut queued_tasks, mission_map.as_ref());
+
+                    // out of work, ask for part of another minion's area
+                    if let (None, Some((_, mothership))) = (current_task, finished) {
+                        let id = seed_id();
+                        let request = D2dMessage::RequestWork { id };
+                        swarm.behaviour_mut().tasks.send_request(&mothership, request.clone());
+                        requests.insert(id, mothership, request, Instant::now());
+                    }
+                },
+                None => {}
             }
 
 
@@ -177,3 +525,110 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 }
+
+// An accepted task with its cells in the order they are searched in.
+struct QueuedTask {
+    task_id: u64,
+    mothership: PeerId,
+    origin: Coordinate,
+    map: TransitMap,
+    cells: Vec<((i32, i32), u32)>,
+}
+
+impl QueuedTask {
+    fn new(task: DelegateTaskMessage, mothership: PeerId) -> Self {
+        QueuedTask {
+            task_id: task.task_id,
+            mothership,
+            origin: task.global_coordinates,
+            map: TransitMap::new(task.global_coordinates, &task.area),
+            cells: search_order(&task.area, task.pattern, task.start),
+        }
+    }
+}
+
+// Queues the areas of its share that a minion takes on without a mothership.
+fn queue_share(
+    state: &Mutex<MinionState>,
+    queued_tasks: &mut VecDeque<QueuedTask>,
+    local_peer_id: PeerId,
+    pattern: PatternId,
+    areas: Vec<(Coordinate, Array2<u32>)>,
+) {
+    let position = {
+        let state = state.lock().unwrap();
+        state.global_position + state.local_position
+    };
+    for (origin, area) in areas {
+        let task = DelegateTaskMessage {
+            task_id: seed_id(),
+            peer_id: local_peer_id,
+            global_coordinates: origin,
+            area,
+            pattern,
+            start: Coordinate { x: position.x - origin.x, y: position.y - origin.y },
+        };
+        println!("Taking on task {} at {:?}", task.task_id, origin);
+        queued_tasks.push_back(QueuedTask::new(task, local_peer_id));
+    }
+}
+
+// Starts searching the next queued task, returning its id and the mothership
+// that assigned it. The route there avoids the no-go cells of the whole
+// mission, or only those of the task until the mission is known.
+fn start_next_task(
+    state: &Mutex<MinionState>,
+    queued_tasks: &mut VecDeque<QueuedTask>,
+    mission_map: Option<&TransitMap>,
+) -> Option<(u64, PeerId)> {
+    let task = queued_tasks.pop_front()?;
+    let mut state = state.lock().unwrap();
+
+    // fly to the first cell around the no-go cells, or search it at once if there is no way there
+    let position = state.global_position + state.local_position;
+    let first = task.cells.first().map(|&((x, y), _)| task.origin + Coordinate { x, y });
+    let map = mission_map.unwrap_or(&task.map);
+    state.transit = match first.and_then(|first| map.plan(position, first)) {
+        Some(route) => {
+            println!("Flying {:.1} cells to task {}", route.length, task.task_id);
+            let origin = task.origin;
+            route.waypoints.into_iter().map(|w| Coordinate { x: w.x - origin.x, y: w.y - origin.y }).collect()
+        },
+        None => VecDeque::new(),
+    };
+    state.mission_area = Some(task.cells.into_iter());
+    state.global_position = task.origin;
+    state.area_exhausted = false;
+    state.ready = true;
+    println!("Searching task {}", task.task_id);
+    Some((task.task_id, task.mothership))
+}
+
+// Gives up the second half of the cells left in a task, returning the global
+// position of the first cell given up. These cells are never searched here,
+// even if the reply is lost, so ownership passes to the mothership at once.
+fn release_half(
+    state: &Mutex<MinionState>,
+    current_task: Option<(u64, PeerId)>,
+    queued_tasks: &mut VecDeque<QueuedTask>,
+    task_id: u64,
+) -> Option<Coordinate> {
+    if current_task.is_some_and(|(id, _)| id == task_id) {
+        let mut state = state.lock().unwrap();
+        let mut cells = state.mission_area.take().map(|c| c.collect()).unwrap_or_default();
+        let from = split_off_half(&mut cells);
+        state.mission_area = Some(cells.into_iter());
+        return from.map(|(x, y)| state.global_position + Coordinate { x, y });
+    }
+
+    let task = queued_tasks.iter_mut().find(|t| t.task_id == task_id)?;
+    split_off_half(&mut task.cells).map(|(x, y)| task.origin + Coordinate { x, y })
+}
+
+fn split_off_half(cells: &mut Vec<((i32, i32), u32)>) -> Option<(i32, i32)> {
+    if cells.len() < 2 {
+        return None;
+    }
+    let released = cells.split_off(cells.len().div_ceil(2));
+    released.first().map(|(cell, _)| *cell)
+}
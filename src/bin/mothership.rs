@@ -1,18 +1,25 @@
-use async_std::channel::unbounded;
 use futures::{prelude::*, select};
 use libp2p::gossipsub::{GossipsubEvent, IdentTopic as Topic, MessageAuthenticity, ValidationMode};
-use libp2p::{gossipsub, identity, swarm::SwarmEvent, PeerId};
+use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::{gossipsub, identity, swarm::SwarmEvent, PeerId, Swarm};
 use ndarray::Array2;
-use serde_json;
-use std::collections::HashMap;
-use std::collections::VecDeque;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::time::Duration;
-use async_std::task;
+use std::iter;
+use std::time::{Duration, Instant};
 
+use libd2d::acting::{AgentTours, DeparturePolicy, OnlineTour};
+use libd2d::auction::{self, Allocation, Auctioneer, Lot};
+use libd2d::delivery::seed_id;
+use libd2d::mask;
+use libd2d::partition::assign_regions;
+use libd2d::progress::reassign;
+use libd2d::routing::route_agents;
 use libd2d::{
-    split_mission_area, Coordinate, DelegateTaskMessage, DelegateTasks, MissionStatus,
-    MothershipState,
+    ticks, CodecId, Coordinate, D2dBehaviour, D2dError, D2dEvent, D2dMessage,
+    DelegateTaskMessage, DelegateTasks, Delivery, FailureDetector, Messenger, MinionStatus,
+    MissionStatus, MothershipState, Outbox, PartitionerId, PatternId, PeerVersions, Role, TourPlannerId, PROTOCOL_VERSION,
 };
 
 #[async_std::main]
@@ -22,12 +29,11 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         position: Coordinate { x: -1, y: -1 },
         mission_status: MissionStatus::Pending,
         mission_area: None,
-        delegate_tasks: DelegateTasks {
-            minions: HashMap::new(),
-            total: 0,
-            complete: 0,
-        },
+        search_pattern: PatternId::default(),
+        delegate_tasks: DelegateTasks::default(),
         points_of_interest: VecDeque::new(),
+        peer_versions: PeerVersions::default(),
+        liveness: FailureDetector::from_env()?,
     };
 
     // Create a random PeerId
@@ -35,17 +41,40 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let local_peer_id = PeerId::from(local_key.public());
     println!("Local peer id: {:?}", local_peer_id);
 
+    // Select the wire codec, JSON unless overridden with D2D_CODEC
+    let codec = CodecId::from_env()?;
+    let mut messenger = Messenger::new(local_peer_id, codec.codec());
+    println!("Encoding messages with {}", messenger.codec().id());
+
+    // Select how missions are partitioned, strips unless overridden with D2D_PARTITION
+    let partitioner = PartitionerId::from_env()?.partitioner();
+    println!("Partitioning missions into {}", partitioner.id());
+
+    // Select how regions are handed out, centrally unless overridden with D2D_ALLOCATION
+    let allocation = Allocation::from_env()?;
+    println!("Allocating regions by {}", allocation);
+    let mut auction: Option<Auctioneer> = None;
+    // Points of interest won by every actuator, while they are auctioned
+    let mut poi_tours: Option<HashMap<PeerId, Vec<Coordinate>>> = None;
+
+    // Select how points of interest are toured, exactly for few unless overridden with D2D_TOUR
+    let tour_planner = TourPlannerId::from_env()?.planner();
+    println!("Planning tours with {}", tour_planner.id());
+
     // Set up an encrypted TCP Transport over the Mplex and Yamux protocols
     let transport = libp2p::development_transport(local_key.clone()).await?;
 
     // Create a Gossipsub topic
+    let topic_handshake = Topic::new("handshake");
+    let topic_chunk = Topic::new("chunk");
     let topic_new_mission = Topic::new("new_mission");
-    let topic_delegate_task = Topic::new("delegate_task");
     let topic_poi = Topic::new("poi");
-    let topic_task_complete = Topic::new("task_complete");
+    let topic_report = Topic::new("reporting");
+    let topic_heartbeat = Topic::new("heartbeat");
     let topic_discovery = Topic::new("discovery");
     let topic_report_mothership = Topic::new("reporting_mothership");
     let topic_mission_complete = Topic::new("mission_complete");
+    let topic_auction = Topic::new("auction");
 
     // Create a Swarm to manage peers and events
     let mut swarm = {
@@ -61,69 +90,233 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             gossipsub::Gossipsub::new(MessageAuthenticity::Signed(local_key), gossipsub_config)
                 .expect("Correct configuration");
 
+        gossipsub.subscribe(&topic_handshake).unwrap();
+        gossipsub.subscribe(&topic_chunk).unwrap();
         gossipsub.subscribe(&topic_new_mission).unwrap();
-        gossipsub.subscribe(&topic_delegate_task).unwrap();
         gossipsub.subscribe(&topic_poi).unwrap();
-        gossipsub.subscribe(&topic_task_complete).unwrap();
+        gossipsub.subscribe(&topic_report).unwrap();
+        gossipsub.subscribe(&topic_heartbeat).unwrap();
         gossipsub.subscribe(&topic_discovery).unwrap();
         gossipsub.subscribe(&topic_report_mothership).unwrap();
         gossipsub.subscribe(&topic_mission_complete).unwrap();
+        gossipsub.subscribe(&topic_auction).unwrap();
 
-        libp2p::Swarm::new(transport, gossipsub, local_peer_id)
+        libp2p::Swarm::new(transport, D2dBehaviour::new(gossipsub, codec), local_peer_id)
     };
 
     swarm
         .listen_on("/ip4/127.0.0.1/tcp/60740".parse().unwrap())
         .unwrap();
 
-    let (tx, mut rx) = unbounded::<Option<Coordinate>>();
+    // Points of interest are visited as they are found, once the departure policy sets the mothership out
+    let mut acting = OnlineTour::new(DeparturePolicy::from_env()?, state.position);
+    println!("Setting out for points of interest {}", acting.policy);
+    // Tours of the minions that act on points of interest too
+    let mut agent_tours = AgentTours::default();
 
-    // let mut tx_stream = rx.fuse();
+    let mut delegation = Delegation {
+        requests: Outbox::default(),
+        next_id: seed_id(),
+        work_requests: HashSet::new(),
+    };
+
+    let mut chunk_ticks = ticks(Duration::from_secs(1));
+    let mut retry_ticks = ticks(Duration::from_secs(1));
+    let mut liveness_ticks = ticks(Duration::from_secs(1));
+    let mut acting_ticks = ticks(Duration::from_millis(100));
+    let mut auction_ticks = ticks(auction::ROUND);
 
     loop {
         select! {
-            event = rx.select_next_some() => {
-                match event {
-                    Some(coordinate) => {
-                        let serialized = serde_json::to_string(&coordinate).unwrap();
-                        if let Err(e) = swarm
-                            .behaviour_mut()
-                            .publish(topic_report_mothership.clone(), serialized.as_bytes())
-                        {
-                            println!("Publish error: {:?}", e);
+            _ = chunk_ticks.select_next_some() => {
+                if let Err(e) = messenger.request_missing(&mut swarm.behaviour_mut().gossipsub) {
+                    println!("Publish error: {}", e);
+                }
+            },
+            _ = retry_ticks.select_next_some() => {
+                for delivery in delegation.requests.due(Instant::now()) {
+                    match delivery {
+                        Delivery::Resend { id, peer_id, message } => {
+                            println!("Resending request {} to {}", id, peer_id);
+                            swarm.behaviour_mut().tasks.send_request(&peer_id, message);
+                        },
+                        Delivery::GaveUp { id, peer_id, message } => {
+                            println!("Giving up on request {} to {}", id, peer_id);
+                            // the minion is most likely dead, its tasks are withdrawn when detected
+                            if let D2dMessage::StealWork { task_id, .. } = message {
+                                state.delegate_tasks.stealing.remove(&task_id);
+                                search_complete(&mut state);
+                            }
+                        },
+                    }
+                }
+            },
+            _ = liveness_ticks.select_next_some() => {
+                for (peer_id, status) in state.liveness.check(Instant::now()) {
+                    println!("Minion {} is {:?}", peer_id, status);
+                    if status != MinionStatus::Dead {
+                        continue;
+                    }
+                    state.delegate_tasks.minions.remove(&peer_id);
+
+                    // the points of interest it was to act on fall back to the mothership
+                    for poi in agent_tours.withdraw(&peer_id) {
+                        acting.add(poi, Instant::now(), tour_planner.as_ref());
+                    }
+
+                    // hand what the minion left unsearched to the minions still alive
+                    let mut remaining = Vec::new();
+                    for (task_id, minion) in state.delegate_tasks.unfinished() {
+                        if minion == peer_id {
+                            remaining.extend(delegation.withdraw(&mut swarm, &mut state, task_id, None));
+                        }
+                    }
+                    delegation.redistribute(&mut swarm, &mut state, remaining);
+                    search_complete(&mut state);
+                }
+            },
+            _ = auction_ticks.select_next_some() => {
+                if let Some(auctioneer) = auction.as_mut() {
+                    let mut messages = Vec::new();
+                    if let Some((award, lot)) = auctioneer.close() {
+                        match (lot, poi_tours.as_mut()) {
+                            (Lot::Poi(poi), Some(tours)) => {
+                                println!("{} won {:?}", award.winner, poi);
+                                tours.entry(award.winner).or_default().push(poi);
+                            },
+                            (Lot::Poi(_), None) => {},
+                            (Lot::Area { origin, .. }, _) => {
+                                // the area is gone if a rebalance handed it out in the meantime
+                                let unassigned = &mut state.delegate_tasks.unassigned;
+                                if let Some(i) = unassigned.iter().position(|(o, _)| *o == origin) {
+                                    let (origin, area) = unassigned.remove(i);
+                                    println!("{} won {:?}", award.winner, origin);
+                                    delegation.delegate(&mut swarm, &mut state, award.winner, origin, area);
+                                }
+                            },
+                        }
+                        messages.push(D2dMessage::Award(award));
+                    }
+
+                    let over = auctioneer.is_finished() || auctioneer.is_abandoned();
+                    if auctioneer.is_abandoned() {
+                        println!("Nobody is bidding, assigning the rest of the mission");
+                        let remaining = std::mem::take(&mut state.delegate_tasks.unassigned);
+                        delegation.redistribute(&mut swarm, &mut state, remaining);
+                        // and acting on the points of interest left
+                        for (_, lot) in auctioneer.announce().lots {
+                            if let Lot::Poi(poi) = lot {
+                                acting.add(poi, Instant::now(), tour_planner.as_ref());
+                            }
+                        }
+                    } else if !over {
+                        messages.push(D2dMessage::Announce(auctioneer.announce()));
+                    }
+                    for message in messages {
+                        if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &message) {
+                            println!("Publish error: {}", e);
+                        };
+                    }
+                    if over {
+                        auction = None;
+                    }
+                    // every actuator acts on the points it won in the order it won them, as it bid
+                    // from where the previous one left it
+                    if let Some(tours) = poi_tours.take_if(|_| over) {
+                        let mut assigned = Vec::new();
+                        for (agent, tour) in tours {
+                            if !state.delegate_tasks.minions.contains_key(&agent) {
+                                for poi in tour {
+                                    acting.add(poi, Instant::now(), tour_planner.as_ref());
+                                }
+                                continue;
+                            }
+                            println!("{} acts on {} points of interest", agent, tour.len());
+                            let message = D2dMessage::AgentTour { agent, tour: tour.clone() };
+                            if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &message) {
+                                println!("Publish error: {}", e);
+                            };
+                            assigned.push((agent, tour));
+                        }
+                        agent_tours.assign(assigned);
+                    }
+                }
+            },
+            _ = acting_ticks.select_next_some() => {
+                // once searched, the points of interest left are split with the minions that can act on them
+                if matches!(state.mission_status, MissionStatus::Acting) && !agent_tours.is_assigned() && auction.is_none() {
+                    let agents = state.delegate_tasks.actuators();
+                    let pois = acting.take_pending();
+                    // auctioned to the actuators if the regions were, those nobody bids for are left to the mothership
+                    if allocation == Allocation::Auction && !agents.is_empty() && !pois.is_empty() {
+                        let auctioneer = Auctioneer::new(seed_id(), pois.into_iter().map(Lot::Poi).collect());
+                        println!("Auctioning {} points of interest", auctioneer.announce().lots.len());
+                        let announcement = D2dMessage::Announce(auctioneer.announce());
+                        if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &announcement) {
+                            println!("Publish error: {}", e);
                         };
-                    },
-                    None => {
-                        if let Err(e) = swarm
-                            .behaviour_mut()
-                            .publish(topic_mission_complete.clone(), "".as_bytes())
-                        {
-                            println!("Publish error: {:?}", e);
+                        auction = Some(auctioneer);
+                        poi_tours = Some(HashMap::new());
+                        continue;
+                    }
+                    let positions = iter::once(acting.position).chain(agents.iter().map(|(_, p)| *p)).collect::<Vec<_>>();
+                    let mut tours = route_agents(&positions, &pois, tour_planner.as_ref()).into_iter();
+                    for poi in tours.next().map(|tour| tour.order).unwrap_or_default() {
+                        acting.add(poi, Instant::now(), tour_planner.as_ref());
+                    }
+                    let tours = agents.into_iter().map(|(peer_id, _)| peer_id).zip(tours.map(|tour| tour.order)).collect::<Vec<_>>();
+                    for (agent, tour) in tours.iter().filter(|(_, tour)| !tour.is_empty()) {
+                        println!("{} acts on {} points of interest", agent, tour.len());
+                        let message = D2dMessage::AgentTour { agent: *agent, tour: tour.clone() };
+                        if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &message) {
+                            println!("Publish error: {}", e);
+                        };
+                    }
+                    agent_tours.assign(tours);
+                }
+
+                if matches!(state.mission_status, MissionStatus::InProgress | MissionStatus::Acting) {
+                    let searched = matches!(state.mission_status, MissionStatus::Acting);
+                    let message = match acting.next(Instant::now(), searched, tour_planner.as_ref()) {
+                        Some(poi) => {
+                            println!("Acting on {:?}", poi);
+                            Some(D2dMessage::ReportMothership(poi))
+                        },
+                        None if searched && acting.is_idle() && agent_tours.is_complete() && poi_tours.is_none() => {
+                            println!("Finished acting on {} points of interest.", acting.visited());
+                            state.mission_status = MissionStatus::Complete;
+                            Some(D2dMessage::MissionComplete)
+                        },
+                        None => None,
+                    };
+                    if let Some(message) = message {
+                        if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &message) {
+                            println!("Publish error: {}", e);
                         };
                     }
                 }
-                
             },
             event = swarm.select_next_some() => match event {
 
-                SwarmEvent::Behaviour(GossipsubEvent::Subscribed {
-                    peer_id,
+                SwarmEvent::Behaviour(D2dEvent::Gossipsub(GossipsubEvent::Subscribed {
+                    peer_id: _,
                     topic,
-                }) => {
+                })) => {
                     match topic {
-                        hash if hash == topic_delegate_task.hash() => {
-                            // update delegate tasks according to peers subscribed to topic
-                            state.delegate_tasks.minions.entry(peer_id).or_insert(Coordinate {x:0, y:0});
+                        hash if hash == topic_handshake.hash() => {
+                            let hello = D2dMessage::Hello { version: PROTOCOL_VERSION, role: Role::Mothership };
+                            if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &hello) {
+                                println!("Publish error: {}", e);
+                            }
                         },
                         hash if hash == topic_discovery.hash() => {
 
-                            let peer_list = serde_json::to_string(&state.delegate_tasks.minions).unwrap();
+                            let peer_list = D2dMessage::Discovery {
+                                minions: state.delegate_tasks.minions.clone(),
+                            };
 
-                            if let Err(e) = swarm
-                                .behaviour_mut()
-                                .publish(topic_discovery.clone(), peer_list.as_bytes())
-                            {
-                                println!("Publish error: {:?}", e);
+                            if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &peer_list) {
+                                println!("Publish error: {}", e);
                             }
                         },
 
@@ -131,85 +324,235 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                     }
                 },
 
-                SwarmEvent::Behaviour(GossipsubEvent::Message {
+                SwarmEvent::Behaviour(D2dEvent::Gossipsub(GossipsubEvent::Message {
                     propagation_source: _peer_id,
                     message_id: _id,
                     message,
-                }) => {
-                    match message.topic.as_str() {
+                })) => {
+                    let source = message.source;
+                    let message = match messenger.receive(&mut swarm.behaviour_mut().gossipsub, &message) {
+                        Ok(Some(message)) => message,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            if let (D2dError::UnsupportedVersion(version), Some(peer_id)) = (&e, source) {
+                                state.peer_versions.record(peer_id, *version);
+                                state.delegate_tasks.minions.remove(&peer_id);
+                                state.liveness.remove(&peer_id);
+                            }
+                            println!("Dropping message: {}", e);
+                            continue;
+                        }
+                    };
 
-                        "new_mission" => {
+                    match message {
 
-                            let area: Array2<u32> = serde_json::from_str(&String::from_utf8_lossy(&message.data)).unwrap();
+                        D2dMessage::Hello { version, role } => {
+                            if let Some(peer_id) = source {
+                                let compatibility = state.peer_versions.hello(peer_id, version, role);
+                                println!("{:?} {} speaks {}: {:?}", role, peer_id, version, compatibility);
+                                // only compatible minions are delegated tasks
+                                if state.peer_versions.is_minion(&peer_id) {
+                                    state.liveness.heartbeat(peer_id, Instant::now());
+                                    if let Entry::Vacant(minion) = state.delegate_tasks.minions.entry(peer_id) {
+                                        minion.insert(Coordinate {x:0, y:0});
+                                        delegation.rebalance(&mut swarm, &mut state, peer_id);
+                                    }
+                                }
+                            }
+                        },
+
+                        D2dMessage::Capabilities(capabilities) => {
+                            if let Some(peer_id) = source.filter(|p| state.peer_versions.is_minion(p)) {
+                                if !capabilities.is_valid() {
+                                    println!("Ignoring invalid capabilities from {}: {:?}", peer_id, capabilities);
+                                    continue;
+                                }
+                                println!("{} searches {} cells per second", peer_id, capabilities.throughput());
+                                let previous = state.delegate_tasks.capabilities.insert(peer_id, capabilities);
+                                // later splits follow the new capabilities, a minion faster than
+                                // thought runs out of work early and steals some
+                                if previous.unwrap_or_default() != capabilities && state.delegate_tasks.minions.contains_key(&peer_id) {
+                                    delegation.rebalance(&mut swarm, &mut state, peer_id);
+                                }
+                            }
+                        },
+
+                        D2dMessage::NewMission { area, pattern } => {
+
+                            // the operator publishes a mission again for every minion it discovers,
+                            // searching it twice would undo points of interest already acted on
+                            let under_way = matches!(state.mission_status, MissionStatus::InProgress | MissionStatus::Acting);
+                            if under_way && state.search_pattern == pattern && state.mission_area.as_ref() == Some(&area) {
+                                continue;
+                            }
 
                             // Update state
                             state.mission_status = MissionStatus::InProgress;
                             state.mission_area = Some(area.clone());
-                            let minion_count = state.delegate_tasks.minions.len();
-                            state.delegate_tasks.total = minion_count as u32;
+                            state.search_pattern = pattern;
+                            acting.reset();
+                            agent_tours = AgentTours::default();
+                            if poi_tours.take().is_some() {
+                                auction = None;
+                            }
+                            println!("Searching the mission in a {} pattern", pattern);
+                            let minions = state.delegate_tasks.minions.keys().copied().collect::<Vec<_>>();
+                            let participants = state.delegate_tasks.participants(&minions, Coordinate { x: 0, y: 0 }, &area);
 
-                            // Split up area amongst minions
-                            let splits = split_mission_area(area.clone(), minion_count);
-                            for (i, split) in splits.clone().iter().enumerate() {
-                                println!("\nSplit {} with index {:?}: \n{}", i, split.0, split.1);
-                            };
-                            let zipped = splits.iter().zip(state.delegate_tasks.minions.clone());
-
-
-                            for (subarea, minion) in zipped {
-                                let task_message = DelegateTaskMessage {
-                                    peer_id: minion.0.clone(),
-                                    global_coordinates: Coordinate { x: subarea.0[0], y: subarea.0[1] },
-                                    area: subarea.1.to_owned(),
-                                };
-                                let task_message = serde_json::to_string(&task_message).unwrap();
-                                if let Err(e) = swarm
-                                    .behaviour_mut()
-                                    .publish(topic_delegate_task.clone(), task_message.as_bytes())
-                                {
-                                    println!("Publish error: {:?}", e);
+                            // Split up area amongst minions, in proportion to their capabilities
+                            let regions = partitioner.partition(&area, &participants);
+                            match allocation {
+                                // and send each to the region it reaches soonest
+                                Allocation::Central => {
+                                    let capabilities = state.delegate_tasks.capabilities_of(&minions);
+                                    for (minion, region) in minions.into_iter().zip(assign_regions(regions, &participants, &capabilities)) {
+                                        for (index, subarea) in region {
+                                            println!("\nSplit for {} with index {:?}: \n{}", minion, index, subarea);
+                                            let origin = Coordinate { x: index[0], y: index[1] };
+                                            delegation.delegate(&mut swarm, &mut state, minion, origin, subarea);
+                                        }
+                                    }
+                                },
+                                // or let the minions bid for the regions, held back until they are won
+                                Allocation::Auction => {
+                                    let mut lots = Vec::new();
+                                    for (index, subarea) in regions.into_iter().flatten() {
+                                        let cells = mask::searchable_cells(subarea.view());
+                                        if cells == 0 {
+                                            continue;
+                                        }
+                                        let origin = Coordinate { x: index[0], y: index[1] };
+                                        let (rows, cols) = subarea.dim();
+                                        lots.push(Lot::Area { origin, rows, cols, cells });
+                                        state.delegate_tasks.unassigned.push((origin, subarea));
+                                    }
+                                    let auctioneer = Auctioneer::new(seed_id(), lots);
+                                    println!("Auctioning {} regions", auctioneer.announce().lots.len());
+                                    let announcement = D2dMessage::Announce(auctioneer.announce());
+                                    if let Err(e) = messenger.publish(&mut swarm.behaviour_mut().gossipsub, &announcement) {
+                                        println!("Publish error: {}", e);
+                                    };
+                                    auction = Some(auctioneer);
+                                },
+                            }
+                        },
+
+                        D2dMessage::Bid(bid) => {
+                            if let (Some(auctioneer), Some(peer_id)) = (auction.as_mut(), source.filter(|p| state.peer_versions.is_compatible(p))) {
+                                auctioneer.bid(peer_id, bid);
+                            }
+                        },
+
+                        D2dMessage::Heartbeat(heartbeat) => {
+                            // minions that were declared dead rejoin once heard from again
+                            if let Some(peer_id) = source.filter(|p| state.peer_versions.is_minion(p)) {
+                                state.liveness.heartbeat(peer_id, Instant::now());
+                                if state.delegate_tasks.minions.insert(peer_id, heartbeat.position).is_none() {
+                                    delegation.rebalance(&mut swarm, &mut state, peer_id);
                                 }
                             }
                         },
 
-                        "poi" => {
-                            let poi: Coordinate = serde_json::from_str(&String::from_utf8_lossy(&message.data)).unwrap();
-                            state.points_of_interest.push_front(poi);
+                        D2dMessage::Report { position, .. } => {
+                            if let Some(peer_id) = source {
+                                state.delegate_tasks.report(peer_id, position);
+                            }
                         },
 
-                        "task_complete" => {
-                            state.delegate_tasks.complete += 1;
-                            if state.delegate_tasks.complete == state.delegate_tasks.total {
-                                let mut pois = state.points_of_interest.clone();
-                                let mut current_position = state.position.clone();
-                                
-                                let thread_tx = tx.clone();
-
-                                let _ = task::spawn(async move {
-                                    while !pois.is_empty() {
-                                        let mut min = (state.position.clone(), 10000f64);
-                                        for poi in &pois {
-                                            let distance = current_position.manhatten_distance(*poi);
-                                            if distance < min.1 {
-                                                min = (*poi, distance);
-                                            }
-                                        };
-                                        current_position = min.0;
-                                        pois.retain(|c| *c != min.0);
-                                        thread_tx.send(Some(min.0)).await.expect("receiver hung up");
-                                        println!("Acting on {:?}", current_position);
-                                        task::sleep(Duration::from_millis(100)).await;
-                                    };
-                                    thread_tx.send(None).await.expect("receiver hung up");
-                                    println!("Finished acting.");
-                                });
+                        D2dMessage::ReportMothership(poi) => {
+                            if let Some(peer_id) = source.filter(|p| agent_tours.serviced(*p, poi)) {
+                                println!("{} acted on {:?}, {} to go", peer_id, poi, agent_tours.remaining(&peer_id));
                             }
-                        }
+                        },
+
+                        D2dMessage::Poi(poi) => {
+                            state.points_of_interest.push_front(poi);
+                            if acting.add(poi, Instant::now(), tour_planner.as_ref()) && acting.is_underway() {
+                                println!("Replanned the tour for {:?}", poi);
+                            }
+                        },
 
-                        _ => println!("Unknown topic"),
+                        _ => println!("Unexpected message"),
                     };
                 },
 
+                SwarmEvent::Behaviour(D2dEvent::Tasks(RequestResponseEvent::Message {
+                    peer,
+                    message: RequestResponseMessage::Request { request, channel, .. },
+                })) => {
+                    match request {
+                        D2dMessage::TaskComplete { task_id } => {
+                            // acknowledge retransmissions too, the first ack may have been lost
+                            if swarm.behaviour_mut().tasks.send_response(channel, D2dMessage::Ack { id: task_id }).is_err() {
+                                println!("Ack to {} failed: connection closed", peer);
+                            }
+                            if state.delegate_tasks.complete_task(task_id) {
+                                println!("{} completed task {}", peer, task_id);
+                                search_complete(&mut state);
+                            }
+                        },
+                        D2dMessage::RequestWork { id } => {
+                            if swarm.behaviour_mut().tasks.send_response(channel, D2dMessage::Ack { id }).is_err() {
+                                println!("Ack to {} failed: connection closed", peer);
+                            }
+                            if delegation.work_requests.insert(id) {
+                                delegation.steal(&mut swarm, &mut state, peer);
+                            }
+                        },
+                        _ => println!("Unexpected request from {}", peer),
+                    }
+                },
+
+                SwarmEvent::Behaviour(D2dEvent::Tasks(RequestResponseEvent::Message {
+                    peer,
+                    message: RequestResponseMessage::Response { response, .. },
+                })) => {
+                    match response {
+                        D2dMessage::TaskAccepted { task_id } => {
+                            if delegation.requests.ack(task_id).is_some() {
+                                println!("{} accepted task {}", peer, task_id);
+                            }
+                        },
+                        D2dMessage::TaskRejected { task_id, reason } => {
+                            if delegation.requests.ack(task_id).is_some() {
+                                println!("{} rejected task {}: {}", peer, task_id, reason);
+                            }
+                        },
+                        D2dMessage::Ack { id } => {
+                            if delegation.requests.ack(id).is_some() {
+                                println!("{} cancelled task {}", peer, id);
+                            }
+                        },
+                        D2dMessage::WorkReleased { id, task_id, from } => {
+                            if delegation.requests.ack(id).is_none() {
+                                continue;
+                            }
+                            let thief = state.delegate_tasks.stealing.get(&task_id).copied();
+                            let released = state.delegate_tasks.release(task_id, from);
+                            match thief.filter(|p| state.liveness.status(p) == Some(MinionStatus::Alive)) {
+                                Some(thief) => {
+                                    for (origin, area) in released {
+                                        println!("{} hands {:?} over to {}", peer, origin, thief);
+                                        delegation.delegate(&mut swarm, &mut state, thief, origin, area);
+                                    }
+                                },
+                                None => delegation.redistribute(&mut swarm, &mut state, released),
+                            }
+                            search_complete(&mut state);
+                        },
+                        _ => println!("Unexpected response from {}", peer),
+                    }
+                },
+
+                SwarmEvent::Behaviour(D2dEvent::Tasks(RequestResponseEvent::OutboundFailure {
+                    peer,
+                    error,
+                    ..
+                })) => {
+                    // the outbox resends the request once it is due
+                    println!("Request to {} failed: {:?}", peer, error);
+                },
+
                 SwarmEvent::NewListenAddr { address, .. } => {
                     println!("Listening on {:?}", address);
                 }
@@ -218,3 +561,140 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         }
     }
 }
+
+// Requests awaiting a reply from their minion. Tasks and steal requests take
+// their ids from the same counter, as they share the outbox. Requests for work
+// already served are remembered, so that a resent one does not steal twice.
+struct Delegation {
+    requests: Outbox<D2dMessage>,
+    next_id: u64,
+    work_requests: HashSet<u64>,
+}
+
+impl Delegation {
+    // Sends a task directly to its minion rather than broadcasting it.
+    fn delegate(
+        &mut self,
+        swarm: &mut Swarm<D2dBehaviour>,
+        state: &mut MothershipState,
+        peer_id: PeerId,
+        origin: Coordinate,
+        area: Array2<u32>,
+    ) {
+        // nothing to search in areas that are entirely masked
+        if mask::searchable_cells(area.view()) == 0 {
+            return;
+        }
+        // search from where the minion is
+        let position = state.delegate_tasks.minions.get(&peer_id).copied().unwrap_or(origin);
+        let task = DelegateTaskMessage {
+            task_id: self.next_id(),
+            peer_id,
+            global_coordinates: origin,
+            area,
+            pattern: state.search_pattern,
+            start: Coordinate { x: position.x - origin.x, y: position.y - origin.y },
+        };
+        state.delegate_tasks.assign(&task);
+        self.send(swarm, task.task_id, peer_id, D2dMessage::DelegateTask(task));
+    }
+
+    // Takes back an unfinished task, cancelling it on `minion` if given, and
+    // returns the parts still to be searched.
+    fn withdraw(
+        &mut self,
+        swarm: &mut Swarm<D2dBehaviour>,
+        state: &mut MothershipState,
+        task_id: u64,
+        minion: Option<PeerId>,
+    ) -> Vec<(Coordinate, Array2<u32>)> {
+        self.requests.ack(task_id);
+        if let Some(peer_id) = minion {
+            self.send(swarm, task_id, peer_id, D2dMessage::CancelTask { task_id });
+        }
+        state.delegate_tasks.withdraw_task(task_id)
+    }
+
+    // Splits the remaining areas across the minions that are alive, keeping
+    // them for later if there are none.
+    fn redistribute(
+        &mut self,
+        swarm: &mut Swarm<D2dBehaviour>,
+        state: &mut MothershipState,
+        remaining: Vec<(Coordinate, Array2<u32>)>,
+    ) {
+        let minions = state
+            .delegate_tasks
+            .minions
+            .keys()
+            .filter(|p| state.liveness.status(p) == Some(MinionStatus::Alive))
+            .copied()
+            .collect::<Vec<_>>();
+        if minions.is_empty() {
+            if !remaining.is_empty() {
+                println!("No minion available, {} areas left unassigned", remaining.len());
+            }
+            state.delegate_tasks.unassigned.extend(remaining);
+            return;
+        }
+        let cells = remaining.iter().map(|(_, area)| area.len()).sum();
+        let weights = state.delegate_tasks.weights(&minions, cells);
+        let minions = minions.into_iter().zip(weights).collect::<Vec<_>>();
+        for (minion, origin, area) in reassign(remaining, &minions) {
+            println!("Assigning {:?} to {}", origin, minion);
+            self.delegate(swarm, state, minion, origin, area);
+        }
+    }
+
+    // Gives `minion`, which just joined a mission in progress, its share: the
+    // areas nobody was available for are split across every minion, and if
+    // there are none it is handed half of the busiest minion's task. Tasks
+    // under way are never cancelled, as cells searched but not yet reported
+    // would be searched again.
+    fn rebalance(&mut self, swarm: &mut Swarm<D2dBehaviour>, state: &mut MothershipState, minion: PeerId) {
+        if !matches!(state.mission_status, MissionStatus::InProgress) {
+            return;
+        }
+        let remaining = std::mem::take(&mut state.delegate_tasks.unassigned);
+        if !remaining.is_empty() {
+            println!("Rebalancing {} areas across {} minions", remaining.len(), state.delegate_tasks.minions.len());
+            self.redistribute(swarm, state, remaining);
+        }
+        if !state.delegate_tasks.is_busy(&minion) {
+            self.steal(swarm, state, minion);
+        }
+    }
+
+    // Asks the minion with the most cells left to release half of them for
+    // `thief`, which ran out of work.
+    fn steal(&mut self, swarm: &mut Swarm<D2dBehaviour>, state: &mut MothershipState, thief: PeerId) {
+        if !matches!(state.mission_status, MissionStatus::InProgress) {
+            return;
+        }
+        if let Some((task_id, victim)) = state.delegate_tasks.steal_target(&thief) {
+            state.delegate_tasks.stealing.insert(task_id, thief);
+            let id = self.next_id();
+            self.send(swarm, id, victim, D2dMessage::StealWork { id, task_id });
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+
+    fn send(&mut self, swarm: &mut Swarm<D2dBehaviour>, id: u64, peer_id: PeerId, request: D2dMessage) {
+        swarm.behaviour_mut().tasks.send_request(&peer_id, request.clone());
+        self.requests.insert(id, peer_id, request, Instant::now());
+    }
+}
+
+// Once every task is complete the mission is searched, and all that is left
+// is acting on the points of interest.
+fn search_complete(state: &mut MothershipState) {
+    if matches!(state.mission_status, MissionStatus::InProgress) && state.delegate_tasks.is_complete() {
+        println!("Mission searched, {} points of interest found", state.points_of_interest.len());
+        state.mission_status = MissionStatus::Acting;
+    }
+}
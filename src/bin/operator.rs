@@ -1,12 +1,14 @@
 use futures::{prelude::*, select};
-use libd2d::Coordinate;
+use libd2d::consensus::{self, Coordination};
+use libd2d::{
+    mask, ticks, CodecId, D2dMessage, Messenger, MissionDefinition, PatternId, PeerVersions, Point, Polygon, Role, NO_GO,
+    PROTOCOL_VERSION,
+};
 use libp2p::gossipsub::{GossipsubEvent, IdentTopic as Topic, MessageAuthenticity, ValidationMode};
 use libp2p::{gossipsub, identity, swarm::SwarmEvent, Multiaddr, PeerId};
-use std::collections::HashMap;
 use std::error::Error;
-use std::time::Duration;
-use serde_json;
-use ndarray::Array;
+use std::time::{Duration, Instant};
+use ndarray::{Array, Zip};
 use ndarray_rand::RandomExt;
 use ndarray_rand::rand_distr::Uniform;
 
@@ -17,10 +19,20 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let local_peer_id = PeerId::from(local_key.public());
     println!("Local peer id: {:?}", local_peer_id);
 
+    // Select the wire codec, JSON unless overridden with D2D_CODEC
+    let mut messenger = Messenger::new(local_peer_id, CodecId::from_env()?.codec());
+    println!("Encoding messages with {}", messenger.codec().id());
+
+    // Hand the mission to the mothership, or straight to the minions with D2D_COORDINATION=peer
+    let coordination = Coordination::from_env()?;
+    println!("Coordinated by {}", coordination);
+
     // Set up an encrypted TCP Transport over the Mplex and Yamux protocols
     let transport = libp2p::development_transport(local_key.clone()).await?;
 
     // Create a Gossipsub topic
+    let topic_handshake = Topic::new("handshake");
+    let topic_chunk = Topic::new("chunk");
     let topic_new_mission = Topic::new("new_mission");
     let topic_discovery = Topic::new("discovery");
     let topic_report = Topic::new("reporting");
@@ -41,6 +53,8 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             gossipsub::Gossipsub::new(MessageAuthenticity::Signed(local_key), gossipsub_config)
                 .expect("Correct configuration");
 
+        gossipsub.subscribe(&topic_handshake).unwrap();
+        gossipsub.subscribe(&topic_chunk).unwrap();
         gossipsub.subscribe(&topic_new_mission).unwrap();
         gossipsub.subscribe(&topic_discovery).unwrap();
         gossipsub.subscribe(&topic_report).unwrap();
@@ -52,11 +66,13 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     };
 
     // Dial mothership
-    let address: Multiaddr = "/ip4/127.0.0.1/tcp/60740".parse().unwrap();
-    match swarm.dial(address.clone()) {
-        Ok(_) => println!("Dialed {:?}", address),
-        Err(e) => println!("Dial {:?} failed: {:?}", address, e),
-    };
+    if coordination == Coordination::Mothership {
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/60740".parse().unwrap();
+        match swarm.dial(address.clone()) {
+            Ok(_) => println!("Dialed {:?}", address),
+            Err(e) => println!("Dial {:?} failed: {:?}", address, e),
+        };
+    }
 
     // Hardcode local addresses of minions. This is needed because the ports cannot be shared on the same machine.
     let minion_addresses = [
@@ -67,40 +83,117 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         "/ip4/127.0.0.1/tcp/60745",
         "/ip4/127.0.0.1/tcp/60746",
     ];
+
+    // Without a mothership to discover them, dial every minion that may be running
+    if coordination == Coordination::Peer {
+        for addr in minion_addresses {
+            let address: Multiaddr = addr.parse().unwrap();
+            if let Err(e) = swarm.dial(address.clone()) {
+                println!("Dial {:?} failed: {:?}", address, e);
+            }
+        }
+    }
     
-    // let mission_area = Array::random((12, 8), Uniform::new(0, 2));
-    let mission_area = Array::random((16, 24), Uniform::new(0, 2));
-    // let mission_area = Array::random((53, 67), Uniform::new(0, 2));
-    // let mission_area = Array::random((101, 47), Uniform::new(0, 2));
+    // The mission from D2D_MISSION, or a field with a pond in the middle
+    let mission = match MissionDefinition::from_env()? {
+        Some(mission) => mission,
+        None => MissionDefinition::new(1.0).with_polygon(
+            Polygon::new(vec![Point::new(0.0, 0.0), Point::new(16.0, 0.0), Point::new(16.0, 24.0), Point::new(0.0, 24.0)])
+                .with_hole(vec![Point::new(6.0, 9.0), Point::new(10.0, 9.0), Point::new(10.0, 15.0), Point::new(6.0, 15.0)]),
+        ),
+    };
+    let raster = mission.rasterize()?;
+    println!("Mission of {:?} cells from {:?}", raster.area.dim(), raster.origin);
 
-    let mut result_area = mission_area.clone();
+    // Minions sweep their areas unless D2D_PATTERN asks for another search pattern
+    let pattern = PatternId::from_env()?;
+    println!("Searching in a {} pattern", pattern);
 
-    for cell in result_area.iter_mut() {
-        *cell = 1;
-    }
+    // Hide points of interest in about half of the cells that are to be searched
+    let pois = Array::random(raster.area.dim(), Uniform::new(0, 2));
+    let mission_area = Zip::from(&raster.area).and(&pois).map_collect(|&cell, &poi| {
+        if mask::is_searchable(cell) { poi } else { cell }
+    });
+
+    // Searchable cells start out unsearched, masked cells are expected to stay as they are
+    let mut result_area = mission_area.mapv(|c| if mask::is_searchable(c) { 1 } else { NO_GO });
 
     let mut performence_measure_minion: Option<std::time::Instant> = None;
     let mut performence_measure_mothership: Option<std::time::Instant> = None;
     let mut minion_time: Option<Duration> = None;
     let mut mothership_time: Option<Duration> = None;
-    let mut first_report = true;
     let mut first_mothership_report = true;
+    let mut peer_versions = PeerVersions::default();
+
+    // When the mission is handed to the minions without a mothership, once they have had time to say hello
+    let mut mission_due: Option<Instant> = None;
+    let mut mission_sent = false;
+
+    let mut chunk_ticks = ticks(Duration::from_secs(1));
+    let mut mission_ticks = ticks(Duration::from_secs(1));
 
     loop {
         select! {
+            _ = chunk_ticks.select_next_some() => {
+                if let Err(e) = messenger.request_missing(swarm.behaviour_mut()) {
+                    println!("Publish error: {}", e);
+                }
+            },
+
+            _ = mission_ticks.select_next_some() => {
+                if !mission_sent && mission_due.is_some_and(|due| Instant::now() >= due) {
+                    let new_mission = D2dMessage::NewMission { area: mission_area.clone(), pattern };
+                    match messenger.publish(swarm.behaviour_mut(), &new_mission) {
+                        Ok(()) => {
+                            mission_sent = true;
+                            performence_measure_minion.get_or_insert(Instant::now());
+                        },
+                        Err(e) => println!("Publish error: {}", e),
+                    };
+                }
+            },
 
             event = swarm.select_next_some() => match event {
 
+                SwarmEvent::Behaviour(GossipsubEvent::Subscribed {
+                    peer_id: _,
+                    topic,
+                }) if topic == topic_handshake.hash() => {
+                    let hello = D2dMessage::Hello { version: PROTOCOL_VERSION, role: Role::Operator };
+                    if let Err(e) = messenger.publish(swarm.behaviour_mut(), &hello) {
+                        println!("Publish error: {}", e);
+                    }
+                },
+
                 SwarmEvent::Behaviour(GossipsubEvent::Message {
                     propagation_source: _peer_id,
                     message_id: _id,
                     message,
                 }) => {
-                    match message.topic.as_str() {
+                    let source = message.source;
+                    let message = match messenger.receive(swarm.behaviour_mut(), &message) {
+                        Ok(Some(message)) => message,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            println!("Dropping message: {}", e);
+                            continue;
+                        }
+                    };
+
+                    match message {
+
+                        D2dMessage::Hello { version, role } => {
+                            if let Some(peer_id) = source {
+                                let compatibility = peer_versions.record(peer_id, version);
+                                println!("{:?} {} speaks {}: {:?}", role, peer_id, version, compatibility);
+                            }
+                            if coordination == Coordination::Peer && role == Role::Minion {
+                                mission_due.get_or_insert(Instant::now() + consensus::GATHER);
+                            }
+                        },
 
-                        "discovery" => {
+                        D2dMessage::Discovery { minions } => {
 
-                            let minions: HashMap<PeerId, Coordinate> = serde_json::from_str(&String::from_utf8_lossy(&message.data)).unwrap();
                             for (i, (_, _)) in minions.iter().enumerate() {
                                 let address: Multiaddr = minion_addresses[i].parse().unwrap();
                                 match swarm.dial(address.clone()) {
@@ -108,51 +201,59 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                                     Err(e) => println!("Dial {:?} failed: {:?}", address, e),
                                 };
 
-                                let serialized = serde_json::to_string(&mission_area).unwrap();
+                                let new_mission = D2dMessage::NewMission { area: mission_area.clone(), pattern };
 
-                                if let Err(e) = swarm
-                                    .behaviour_mut()
-                                    .publish(topic_new_mission.clone(), serialized.as_bytes())
-                                {
-                                    println!("Publish error: {:?}", e);
+                                // the search is timed from here, so that it includes flying to the tasks
+                                match messenger.publish(swarm.behaviour_mut(), &new_mission) {
+                                    Ok(()) => {
+                                        performence_measure_minion.get_or_insert(Instant::now());
+                                    },
+                                    Err(e) => println!("Publish error: {}", e),
                                 };
                             }
                         }
 
-                        "reporting" => {
-
-                            if first_report {
-                                performence_measure_minion = Some(std::time::Instant::now());
-                                first_report = false;
+                        D2dMessage::Report { position, poi } => {
+
+                            // negative coordinates wrap around to cells far outside the grid
+                            let cell = [position.x as usize, position.y as usize];
+                            match mission_area.get(cell) {
+                                None => {
+                                    println!("Minion searched {:?} outside the mission", position);
+                                    continue;
+                                },
+                                Some(value) if !mask::is_searchable(*value) => {
+                                    println!("Minion searched masked cell {:?}", position);
+                                    continue;
+                                },
+                                Some(_) => {},
                             }
 
-
-                            let minion_coor: (Coordinate, bool) = serde_json::from_str(&String::from_utf8_lossy(&message.data)).unwrap();
-                            if minion_coor.1 {
-                                result_area[[minion_coor.0.x as usize, minion_coor.0.y as usize]] = 2;
+                            if poi {
+                                result_area[cell] = 2;
                             } else {
-                                result_area[[minion_coor.0.x as usize, minion_coor.0.y as usize]] = 0;
+                                result_area[cell] = 0;
                             };
                             println!("\n{}", result_area);
-                        },
-
-                        "reporting_mothership" => {
 
+                            // the search takes until the last cell is reported, acting may start earlier
                             if let Some(now) = performence_measure_minion {
                                 minion_time = Some(now.elapsed());
                             }
+                        },
+
+                        D2dMessage::ReportMothership(mothership_coor) => {
 
                             if first_mothership_report {
                                 performence_measure_mothership = Some(std::time::Instant::now());
                                 first_mothership_report = false;
                             }
 
-                            let mothership_coor: Coordinate = serde_json::from_str(&String::from_utf8_lossy(&message.data)).unwrap();
                             result_area[[mothership_coor.x as usize, mothership_coor.y as usize]] = 1;
                             println!("\n{}", result_area);
                         },
 
-                        "mission_complete" => {
+                        D2dMessage::MissionComplete => {
 
                             if let Some(now) = performence_measure_mothership {
                                 mothership_time = Some(now.elapsed());
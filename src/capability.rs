@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::time::Duration;
+
+use crate::D2dError;
+
+/// What a minion can do, advertised to the mothership so that faster minions
+/// are handed bigger shares of a mission.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub speed: f64,            // Steps per second.
+    pub sensor_footprint: u32, // Cells sensed in one step.
+    pub endurance: f64,        // Seconds the minion can search for.
+    #[serde(default)]
+    pub actuator: bool, // Whether it can act on points of interest, like the mothership.
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            speed: 10.0,
+            sensor_footprint: 1,
+            endurance: 3600.0,
+            actuator: false,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Capabilities from `D2D_SPEED`, `D2D_SENSOR_FOOTPRINT`, `D2D_ENDURANCE`
+    /// and `D2D_ACTUATOR`, falling back to the defaults for unset variables.
+    pub fn from_env() -> Result<Self, D2dError> {
+        let mut capabilities = Capabilities::default();
+        if let Some(speed) = env_positive("D2D_SPEED")? {
+            capabilities.speed = speed;
+        }
+        if let Some(footprint) = env_positive("D2D_SENSOR_FOOTPRINT")? {
+            if footprint.fract() != 0.0 || footprint > u32::MAX as f64 {
+                return Err(D2dError::InvalidConfig(format!("D2D_SENSOR_FOOTPRINT={} is not a cell count", footprint)));
+            }
+            capabilities.sensor_footprint = footprint as u32;
+        }
+        if let Some(endurance) = env_positive("D2D_ENDURANCE")? {
+            capabilities.endurance = endurance;
+        }
+        match env::var("D2D_ACTUATOR").as_deref() {
+            Ok("1") | Ok("true") => capabilities.actuator = true,
+            Ok("0") | Ok("false") | Err(_) => {}
+            Ok(value) => {
+                return Err(D2dError::InvalidConfig(format!("D2D_ACTUATOR={:?} is not true or false", value)));
+            }
+        }
+        Ok(capabilities)
+    }
+
+    /// Whether a minion could have these capabilities: speed, sensor
+    /// footprint and endurance are positive and finite, as `from_env`
+    /// requires, and so is the range they make up. Capabilities received
+    /// from other peers are checked before they size anyone's share.
+    pub fn is_valid(&self) -> bool {
+        positive(self.speed) && self.sensor_footprint > 0 && positive(self.endurance) && positive(self.range())
+    }
+
+    /// Cells searched per second.
+    pub fn throughput(&self) -> f64 {
+        self.speed * self.sensor_footprint as f64
+    }
+
+    /// Cells the minion can search before running out of endurance.
+    pub fn range(&self) -> f64 {
+        self.throughput() * self.endurance
+    }
+
+    /// Time taken to fly from one cell to the next.
+    pub fn step_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.speed)
+    }
+
+    /// Time spent on every cell.
+    pub fn search_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.throughput())
+    }
+}
+
+/// Number of cells of a `cells` large mission each minion should search:
+/// proportional to their throughput, except that nobody is handed more than
+/// their range while others could take the rest. When the swarm cannot cover
+/// the mission between them, everyone is handed their full range.
+pub fn shares(cells: usize, capabilities: &[Capabilities]) -> Vec<f64> {
+    let mut shares = vec![0.0; capabilities.len()];
+    let mut open = (0..capabilities.len()).collect::<Vec<_>>();
+    let mut left = cells as f64;
+
+    while !open.is_empty() {
+        let throughput = open.iter().map(|&i| capabilities[i].throughput()).sum::<f64>();
+        let (capped, uncapped): (Vec<usize>, Vec<usize>) = open
+            .iter()
+            .partition(|&&i| left * capabilities[i].throughput() / throughput > capabilities[i].range());
+        if capped.is_empty() {
+            for i in uncapped {
+                shares[i] = left * capabilities[i].throughput() / throughput;
+            }
+            break;
+        }
+        for i in capped {
+            shares[i] = capabilities[i].range();
+            left -= shares[i];
+        }
+        open = uncapped;
+    }
+    shares
+}
+
+fn env_positive(name: &str) -> Result<Option<f64>, D2dError> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|v| positive(*v))
+            .map(Some)
+            .ok_or_else(|| D2dError::InvalidConfig(format!("{}={:?} is not a positive number", name, value))),
+        Err(_) => Ok(None),
+    }
+}
+
+fn positive(value: f64) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_follow_throughput() {
+        let slow = Capabilities::default();
+        let fast = Capabilities { speed: 30.0, ..slow };
+        let wide = Capabilities { sensor_footprint: 2, ..slow };
+        assert_eq!(shares(600, &[slow, fast, wide]), vec![100.0, 300.0, 200.0]);
+        assert_eq!(slow.search_interval(), Duration::from_millis(100));
+
+        // the fast minion runs out after 10 seconds, the rest goes to the others
+        let short = Capabilities { endurance: 10.0, ..fast };
+        assert_eq!(shares(600, &[slow, short, wide]), vec![100.0, 300.0, 200.0]);
+        assert_eq!(shares(900, &[slow, short, wide]), vec![200.0, 300.0, 400.0]);
+
+        // nobody can finish, so everyone searches as long as they can
+        let tired = Capabilities { endurance: 1.0, ..slow };
+        assert_eq!(shares(100, &[tired, tired]), vec![10.0, 10.0]);
+        assert!(shares(100, &[]).is_empty());
+    }
+
+    #[test]
+    fn invalid_capabilities() {
+        let valid = Capabilities::default();
+        assert!(valid.is_valid());
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(!Capabilities { speed, ..valid }.is_valid());
+            assert!(!Capabilities { endurance: speed, ..valid }.is_valid());
+        }
+        assert!(!Capabilities { sensor_footprint: 0, ..valid }.is_valid());
+        assert!(!Capabilities { speed: f64::MAX, endurance: f64::MAX, ..valid }.is_valid());
+    }
+}
@@ -0,0 +1,353 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::grid::Bytes;
+use crate::D2dError;
+
+/// Frames larger than this are split into chunks. It leaves room for the
+/// gossipsub envelope within the default `max_transmit_size` of 64 KiB.
+pub const MAX_FRAME_SIZE: usize = 60 * 1024;
+
+/// Payload carried by each chunk. Base64 in JSON frames inflates it by a third.
+pub const CHUNK_SIZE: usize = 32 * 1024;
+
+/// Largest frame sent in chunks, which bounds what a receiver sets aside for
+/// a transfer whatever its chunks claim.
+pub const MAX_TRANSFER_SIZE: usize = 16 * 1024 * 1024;
+
+/// Chunks of the largest transfer.
+pub const MAX_CHUNKS: u32 = MAX_TRANSFER_SIZE.div_ceil(CHUNK_SIZE) as u32;
+
+/// Transfers reassembled at once from one sender. Starting another drops the
+/// one that has been quiet the longest.
+pub const MAX_PARTIALS: usize = 4;
+
+/// One piece of a frame too large for a single publish.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub transfer_id: u64,
+    pub index: u32,
+    pub total: u32,
+    // CRC32 of the reassembled frame
+    pub frame_checksum: u32,
+    // CRC32 of `data`
+    pub checksum: u32,
+    pub data: Bytes,
+}
+
+/// Chunks that did not arrive for a transfer, to be requested from its sender.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResendRequest {
+    pub peer_id: Option<PeerId>,
+    pub transfer_id: u64,
+    pub missing: Vec<u32>,
+}
+
+pub fn split_frame(transfer_id: u64, frame: &[u8], chunk_size: usize) -> Vec<Chunk> {
+    let frame_checksum = crc32fast::hash(frame);
+    let pieces = frame.chunks(chunk_size.max(1)).collect::<Vec<_>>();
+    let total = pieces.len() as u32;
+
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| Chunk {
+            transfer_id,
+            index: index as u32,
+            total,
+            frame_checksum,
+            checksum: crc32fast::hash(data),
+            data: Bytes(data.to_vec()),
+        })
+        .collect()
+}
+
+// Chunks of recently sent transfers, kept so that lost chunks can be resent.
+#[derive(Debug)]
+pub struct OutgoingTransfers {
+    transfers: VecDeque<(u64, Vec<Chunk>)>,
+    capacity: usize,
+}
+
+impl OutgoingTransfers {
+    pub fn new(capacity: usize) -> Self {
+        OutgoingTransfers {
+            transfers: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn insert(&mut self, transfer_id: u64, chunks: Vec<Chunk>) {
+        if self.transfers.len() == self.capacity {
+            self.transfers.pop_front();
+        }
+        self.transfers.push_back((transfer_id, chunks));
+    }
+
+    pub fn resend(&self, transfer_id: u64, missing: &[u32]) -> Vec<Chunk> {
+        self.transfers
+            .iter()
+            .find(|(id, _)| *id == transfer_id)
+            .map(|(_, chunks)| {
+                chunks
+                    .iter()
+                    .filter(|c| missing.contains(&c.index))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+struct Partial {
+    total: u32,
+    frame_checksum: u32,
+    chunks: Vec<Option<Vec<u8>>>,
+    received: u32,
+    last_activity: Instant,
+    requests: u32,
+}
+
+// Collects chunks per sender and transfer until the frame is complete.
+#[derive(Debug)]
+pub struct Reassembler {
+    partials: HashMap<(Option<PeerId>, u64), Partial>,
+    // How long a transfer may stall before missing chunks are requested
+    pub timeout: Duration,
+    // Resend requests made before a transfer is given up
+    pub max_requests: u32,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Reassembler {
+            partials: HashMap::new(),
+            timeout: Duration::from_secs(2),
+            max_requests: 5,
+        }
+    }
+}
+
+impl Reassembler {
+    /// Stores `chunk`, returning the whole frame once its last chunk arrives.
+    pub fn insert(
+        &mut self,
+        source: Option<PeerId>,
+        chunk: Chunk,
+        now: Instant,
+    ) -> Result<Option<Vec<u8>>, D2dError> {
+        if chunk.total == 0 || chunk.total > MAX_CHUNKS || chunk.index >= chunk.total || chunk.data.0.len() > CHUNK_SIZE {
+            return Err(D2dError::CorruptChunk(chunk.transfer_id));
+        }
+        // A corrupt chunk is treated as lost, so that it is requested again.
+        if crc32fast::hash(&chunk.data.0) != chunk.checksum {
+            return Ok(None);
+        }
+
+        let key = (source, chunk.transfer_id);
+        if !self.partials.contains_key(&key) {
+            self.make_room(source, now);
+        }
+        let partial = self.partials.entry(key).or_insert_with(|| Partial {
+            total: chunk.total,
+            frame_checksum: chunk.frame_checksum,
+            chunks: vec![None; chunk.total as usize],
+            received: 0,
+            last_activity: now,
+            requests: 0,
+        });
+        if partial.total != chunk.total || partial.frame_checksum != chunk.frame_checksum {
+            self.partials.remove(&key);
+            return Err(D2dError::CorruptChunk(chunk.transfer_id));
+        }
+
+        partial.last_activity = now;
+        let slot = &mut partial.chunks[chunk.index as usize];
+        if slot.is_none() {
+            *slot = Some(chunk.data.0);
+            partial.received += 1;
+        }
+        if partial.received < partial.total {
+            return Ok(None);
+        }
+
+        let partial = self.partials.remove(&key).unwrap();
+        let frame = partial.chunks.into_iter().flatten().flatten().collect::<Vec<_>>();
+        if crc32fast::hash(&frame) != partial.frame_checksum {
+            return Err(D2dError::CorruptChunk(chunk.transfer_id));
+        }
+        Ok(Some(frame))
+    }
+
+    // Drops the transfers that stalled for good, then the quietest of
+    // `source` if it still has too many under way.
+    fn make_room(&mut self, source: Option<PeerId>, now: Instant) {
+        let expiry = self.timeout * (self.max_requests + 1);
+        self.partials.retain(|_, p| now.duration_since(p.last_activity) < expiry);
+
+        let mut transfers = self
+            .partials
+            .iter()
+            .filter(|((peer_id, _), _)| *peer_id == source)
+            .map(|(key, p)| (p.last_activity, *key))
+            .collect::<Vec<_>>();
+        transfers.sort();
+        let excess = (transfers.len() + 1).saturating_sub(MAX_PARTIALS);
+        for (_, key) in transfers.into_iter().take(excess) {
+            self.partials.remove(&key);
+        }
+    }
+
+    /// Missing chunks of transfers that stalled for longer than `timeout`.
+    /// Transfers still incomplete after `max_requests` requests are dropped.
+    pub fn stalled(&mut self, now: Instant) -> Vec<ResendRequest> {
+        let max_requests = self.max_requests;
+        self.partials.retain(|_, p| p.requests < max_requests);
+
+        let mut requests = Vec::new();
+        for ((peer_id, transfer_id), partial) in self.partials.iter_mut() {
+            if now.duration_since(partial.last_activity) < self.timeout {
+                continue;
+            }
+            partial.requests += 1;
+            partial.last_activity = now;
+            requests.push(ResendRequest {
+                peer_id: *peer_id,
+                transfer_id: *transfer_id,
+                missing: (0..partial.total)
+                    .filter(|i| partial.chunks[*i as usize].is_none())
+                    .collect(),
+            });
+        }
+        requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn reassembles_out_of_order() {
+        let data = frame(10_000);
+        let mut chunks = split_frame(7, &data, 3000);
+        assert_eq!(chunks.len(), 4);
+        chunks.reverse();
+
+        let peer = Some(PeerId::random());
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        let last = chunks.pop().unwrap();
+        for chunk in chunks {
+            assert_eq!(reassembler.insert(peer, chunk, now).unwrap(), None);
+        }
+        assert_eq!(reassembler.insert(peer, last, now).unwrap(), Some(data));
+    }
+
+    #[test]
+    fn requests_missing_chunks() {
+        let data = frame(1000);
+        let chunks = split_frame(1, &data, 100);
+        let mut outgoing = OutgoingTransfers::new(4);
+        outgoing.insert(1, chunks.clone());
+
+        let peer = Some(PeerId::random());
+        let mut reassembler = Reassembler::default();
+        let start = Instant::now();
+        for chunk in chunks.iter().filter(|c| c.index % 3 != 0) {
+            assert_eq!(reassembler.insert(peer, chunk.clone(), start).unwrap(), None);
+        }
+        assert!(reassembler.stalled(start).is_empty());
+
+        let later = start + reassembler.timeout;
+        let requests = reassembler.stalled(later);
+        assert_eq!(
+            requests,
+            vec![ResendRequest { peer_id: peer, transfer_id: 1, missing: vec![0, 3, 6, 9] }]
+        );
+
+        let resent = outgoing.resend(1, &requests[0].missing);
+        assert_eq!(resent.len(), 4);
+        let mut result = None;
+        for chunk in resent {
+            result = reassembler.insert(peer, chunk, later).unwrap();
+        }
+        assert_eq!(result, Some(data));
+    }
+
+    #[test]
+    fn corrupt_chunks_are_rerequested() {
+        let data = frame(300);
+        let mut chunks = split_frame(2, &data, 100);
+        chunks[1].data.0[0] ^= 0xff;
+
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        for chunk in chunks {
+            assert_eq!(reassembler.insert(None, chunk, now).unwrap(), None);
+        }
+        let requests = reassembler.stalled(now + reassembler.timeout);
+        assert_eq!(requests[0].missing, vec![1]);
+    }
+
+    #[test]
+    fn rejects_forged_chunks() {
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        let forged = |index, total| Chunk {
+            transfer_id: 9,
+            index,
+            total,
+            frame_checksum: 0,
+            checksum: crc32fast::hash(&[]),
+            data: Bytes(Vec::new()),
+        };
+        for (index, total) in [(0, u32::MAX), (0, MAX_CHUNKS + 1), (3, 3), (0, 0)] {
+            assert!(matches!(reassembler.insert(None, forged(index, total), now), Err(D2dError::CorruptChunk(9))));
+        }
+        assert!(reassembler.partials.is_empty());
+    }
+
+    #[test]
+    fn caps_transfers_per_sender() {
+        let peer = Some(PeerId::random());
+        let mut reassembler = Reassembler::default();
+        let start = Instant::now();
+        for id in 0..MAX_PARTIALS as u64 + 2 {
+            let chunk = split_frame(id, &frame(300), 100).remove(0);
+            let now = start + Duration::from_millis(id);
+            reassembler.insert(peer, chunk, now).unwrap();
+        }
+        // the oldest transfers of the sender made way, others are unaffected
+        reassembler.insert(None, split_frame(0, &frame(300), 100).remove(0), start).unwrap();
+        assert_eq!(reassembler.partials.len(), MAX_PARTIALS + 1);
+        assert!(!reassembler.partials.contains_key(&(peer, 0)) && !reassembler.partials.contains_key(&(peer, 1)));
+
+        // and transfers that stalled for good make way too
+        let later = start + reassembler.timeout * (reassembler.max_requests + 1) + Duration::from_secs(1);
+        reassembler.insert(peer, split_frame(99, &frame(300), 100).remove(0), later).unwrap();
+        assert_eq!(reassembler.partials.len(), 1);
+    }
+
+    #[test]
+    fn gives_up_after_max_requests() {
+        let chunks = split_frame(3, &frame(300), 100);
+        let mut reassembler = Reassembler::default();
+        let mut now = Instant::now();
+        reassembler.insert(None, chunks[0].clone(), now).unwrap();
+
+        for _ in 0..reassembler.max_requests {
+            now += reassembler.timeout;
+            assert_eq!(reassembler.stalled(now).len(), 1);
+        }
+        now += reassembler.timeout;
+        assert!(reassembler.stalled(now).is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use bincode::Options;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::message::{Envelope, Header, OwnedEnvelope};
+use crate::{config, D2dError, D2dMessage};
+
+/// Identifies the codec of a frame by its first byte. JSON frames carry no
+/// extra prefix: the opening brace of the document doubles as the identifier,
+/// so frames from nodes that predate codecs are still understood.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodecId {
+    #[default]
+    Json,
+    Cbor,
+    Bincode,
+    MessagePack,
+}
+
+pub trait Codec: Send + Sync {
+    fn id(&self) -> CodecId;
+    fn encode(&self, message: &D2dMessage) -> Result<Vec<u8>, D2dError>;
+    fn decode(&self, data: &[u8]) -> Result<D2dMessage, D2dError>;
+}
+
+pub struct Json;
+pub struct Cbor;
+pub struct Bincode;
+pub struct MessagePack;
+
+impl CodecId {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            CodecId::Json => b'{',
+            CodecId::Cbor => 0x01,
+            CodecId::Bincode => 0x02,
+            CodecId::MessagePack => 0x03,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Self, D2dError> {
+        match byte {
+            b'{' => Ok(CodecId::Json),
+            0x01 => Ok(CodecId::Cbor),
+            0x02 => Ok(CodecId::Bincode),
+            0x03 => Ok(CodecId::MessagePack),
+            other => Err(D2dError::UnknownCodec(other)),
+        }
+    }
+
+    /// Codec selected through the `D2D_CODEC` environment variable, JSON if unset.
+    pub fn from_env() -> Result<Self, D2dError> {
+        config::from_env("D2D_CODEC")
+    }
+
+    pub fn codec(self) -> Box<dyn Codec> {
+        match self {
+            CodecId::Json => Box::new(Json),
+            CodecId::Cbor => Box::new(Cbor),
+            CodecId::Bincode => Box::new(Bincode),
+            CodecId::MessagePack => Box::new(MessagePack),
+        }
+    }
+}
+
+impl FromStr for CodecId {
+    type Err = D2dError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(CodecId::Json),
+            "cbor" => Ok(CodecId::Cbor),
+            "bincode" => Ok(CodecId::Bincode),
+            "msgpack" | "messagepack" => Ok(CodecId::MessagePack),
+            _ => Err(D2dError::UnknownCodecName(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for CodecId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CodecId::Json => "json",
+            CodecId::Cbor => "cbor",
+            CodecId::Bincode => "bincode",
+            CodecId::MessagePack => "msgpack",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Codec for Json {
+    fn id(&self) -> CodecId {
+        CodecId::Json
+    }
+
+    fn encode(&self, message: &D2dMessage) -> Result<Vec<u8>, D2dError> {
+        Ok(serde_json::to_vec(&Envelope::new(message))?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<D2dMessage, D2dError> {
+        serde_json::from_slice::<Header>(data)?.check()?;
+        serde_json::from_slice::<OwnedEnvelope>(data)?.into_message()
+    }
+}
+
+impl Codec for Cbor {
+    fn id(&self) -> CodecId {
+        CodecId::Cbor
+    }
+
+    fn encode(&self, message: &D2dMessage) -> Result<Vec<u8>, D2dError> {
+        Ok(serde_cbor::to_vec(&Envelope::new(message))?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<D2dMessage, D2dError> {
+        serde_cbor::from_slice::<Header>(data)?.check()?;
+        serde_cbor::from_slice::<OwnedEnvelope>(data)?.into_message()
+    }
+}
+
+// Bincode is not self-describing, so the header is read as a prefix of the
+// envelope and the field order of both must not change.
+impl Bincode {
+    fn options() -> impl Options {
+        bincode::DefaultOptions::new().allow_trailing_bytes()
+    }
+}
+
+impl Codec for Bincode {
+    fn id(&self) -> CodecId {
+        CodecId::Bincode
+    }
+
+    fn encode(&self, message: &D2dMessage) -> Result<Vec<u8>, D2dError> {
+        Ok(Bincode::options().serialize(&Envelope::new(message))?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<D2dMessage, D2dError> {
+        Bincode::options().deserialize::<Header>(data)?.check()?;
+        Bincode::options().deserialize::<OwnedEnvelope>(data)?.into_message()
+    }
+}
+
+impl Codec for MessagePack {
+    fn id(&self) -> CodecId {
+        CodecId::MessagePack
+    }
+
+    fn encode(&self, message: &D2dMessage) -> Result<Vec<u8>, D2dError> {
+        Ok(rmp_serde::to_vec_named(&Envelope::new(message))?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<D2dMessage, D2dError> {
+        rmp_serde::from_slice::<Header>(data)?.check()?;
+        rmp_serde::from_slice::<OwnedEnvelope>(data)?.into_message()
+    }
+}
+
+/// Encodes `message` with `codec`, prefixed with the codec identifier.
+pub fn encode_frame(codec: &dyn Codec, message: &D2dMessage) -> Result<Vec<u8>, D2dError> {
+    let payload = codec.encode(message)?;
+    match codec.id() {
+        CodecId::Json => Ok(payload),
+        id => {
+            let mut frame = Vec::with_capacity(payload.len() + 1);
+            frame.push(id.to_byte());
+            frame.extend(payload);
+            Ok(frame)
+        }
+    }
+}
+
+/// Decodes a frame produced by any of the codecs.
+pub fn decode_frame(frame: &[u8]) -> Result<D2dMessage, D2dError> {
+    let first = *frame.first().ok_or(D2dError::EmptyFrame)?;
+    match CodecId::from_byte(first)? {
+        CodecId::Json => Json.decode(frame),
+        id => id.codec().decode(&frame[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Coordinate, DelegateTaskMessage, PatternId};
+    use libp2p::PeerId;
+    use ndarray::Array2;
+    use std::collections::HashMap;
+
+    const CODECS: [CodecId; 4] = [
+        CodecId::Json,
+        CodecId::Cbor,
+        CodecId::Bincode,
+        CodecId::MessagePack,
+    ];
+
+    #[test]
+    fn round_trip_all_codecs() {
+        let messages = vec![
+            D2dMessage::Discovery {
+                minions: HashMap::from([(PeerId::random(), Coordinate { x: 1, y: 2 })]),
+            },
+            D2dMessage::DelegateTask(DelegateTaskMessage {
+                task_id: 5,
+                peer_id: PeerId::random(),
+                global_coordinates: Coordinate { x: 4, y: 0 },
+                area: Array2::<u32>::ones((5, 3)),
+                pattern: PatternId::default(),
+                start: Coordinate { x: 0, y: 0 },
+            }),
+            D2dMessage::Report { position: Coordinate { x: 3, y: 3 }, poi: true },
+            D2dMessage::TaskComplete { task_id: 5 },
+        ];
+
+        for id in CODECS {
+            let codec = id.codec();
+            for message in &messages {
+                let frame = encode_frame(codec.as_ref(), message).unwrap();
+                assert_eq!(CodecId::from_byte(frame[0]).unwrap(), id);
+                assert_eq!(&decode_frame(&frame).unwrap(), message, "{}", id);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_codecs_are_smaller() {
+        let message = D2dMessage::NewMission { area: Array2::<u32>::ones((64, 64)), pattern: PatternId::default() };
+        let json = encode_frame(&Json, &message).unwrap().len();
+        for id in [CodecId::Cbor, CodecId::Bincode, CodecId::MessagePack] {
+            let binary = encode_frame(id.codec().as_ref(), &message).unwrap().len();
+            assert!(binary < json, "{} {} >= {}", id, binary, json);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_frames() {
+        assert!(matches!(decode_frame(&[]), Err(D2dError::EmptyFrame)));
+        assert!(matches!(decode_frame(&[0x7f, 0]), Err(D2dError::UnknownCodec(0x7f))));
+        assert!(matches!(decode_frame(&[0x02, 0]), Err(D2dError::Serialization(_))));
+    }
+
+    #[test]
+    fn codec_names() {
+        config::assert_names(CODECS, "yaml");
+    }
+
+    #[test]
+    fn bincode_frame_from_older_minor_version() {
+        // ResendChunks as encoded by a 6.0 build: variant 10, no peer,
+        // transfer 3, missing chunks 0 and 2.
+        let frame = [0x02, 6, 0, 10, 0, 3, 2, 0, 2];
+        assert_eq!(
+            decode_frame(&frame).unwrap(),
+            D2dMessage::ResendChunks { peer_id: None, transfer_id: 3, missing: vec![0, 2] }
+        );
+    }
+}
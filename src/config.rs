@@ -0,0 +1,46 @@
+use std::env;
+use std::str::FromStr;
+
+use crate::D2dError;
+
+/// Setting named by the environment variable `var`, the default if unset.
+pub fn from_env<T>(var: &str) -> Result<T, D2dError>
+where
+    T: FromStr<Err = D2dError> + Default,
+{
+    match env::var(var) {
+        Ok(name) => name.parse(),
+        Err(_) => Ok(T::default()),
+    }
+}
+
+/// Checks that every setting parses back from its name and that `unknown` is rejected.
+#[cfg(test)]
+pub(crate) fn assert_names<T>(settings: impl IntoIterator<Item = T>, unknown: &str)
+where
+    T: FromStr + std::fmt::Display + PartialEq + std::fmt::Debug,
+{
+    for setting in settings {
+        let name = setting.to_string();
+        assert_eq!(name.parse::<T>().ok(), Some(setting), "{}", name);
+        assert!(name.to_uppercase().parse::<T>().is_ok(), "{}", name);
+    }
+    assert!(unknown.parse::<T>().is_err());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CodecId;
+
+    #[test]
+    fn settings_from_env() {
+        assert_eq!(from_env::<CodecId>("D2D_CONFIG_TEST_UNSET").unwrap(), CodecId::default());
+
+        env::set_var("D2D_CONFIG_TEST", "cbor");
+        assert_eq!(from_env::<CodecId>("D2D_CONFIG_TEST").unwrap(), CodecId::Cbor);
+        env::set_var("D2D_CONFIG_TEST", "yaml");
+        assert!(from_env::<CodecId>("D2D_CONFIG_TEST").is_err());
+        env::remove_var("D2D_CONFIG_TEST");
+    }
+}
@@ -0,0 +1,561 @@
+use libp2p::PeerId;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::capability;
+use crate::config;
+use crate::mask;
+use crate::partition::{assign_regions, AreaPartitioner, Participant};
+use crate::progress::reassign;
+use crate::{Capabilities, Coordinate, D2dError, PatternId};
+
+/// How often peers publish their commit, until the mission is complete.
+pub const ROUND: Duration = Duration::from_secs(1);
+
+/// How long peers collect the others taking part in a mission before
+/// committing to them.
+pub const GATHER: Duration = Duration::from_secs(2);
+
+/// How long a peer waits for the others to commit to a round before leaving
+/// out those that have not.
+pub const SILENCE: Duration = Duration::from_secs(5);
+
+/// How many rounds a commit may be ahead of a peer. Peers only move on one
+/// round at a time, so commits further ahead are forged or corrupt.
+pub const MAX_ROUND_LEAP: u32 = 16;
+
+/// Who splits missions between the minions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Coordination {
+    // The mothership, which minions report to.
+    #[default]
+    Mothership,
+    // The minions themselves, agreeing on the split without a mothership.
+    Peer,
+}
+
+/// A minion taking part in a mission, as it introduced itself to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub peer_id: PeerId,
+    pub position: Coordinate,
+    pub capabilities: Capabilities,
+}
+
+/// The members a peer takes part in a mission with, as it sees them in a
+/// round. Round 0 only introduces the peer, later rounds commit to the
+/// members.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Commit {
+    pub mission: u64,
+    pub round: u32,
+    pub members: Vec<Member>,
+}
+
+/// Agreement of a peer with the others on who takes part in a mission.
+///
+/// Peers first gather whoever introduces themselves, then commit to the
+/// members they know of. A peer agrees once every member has committed to
+/// the same members in the same round. Members that commit to others are
+/// intersected with and members that stay silent are left out, and a new
+/// round is started, so every round can only shrink the members. A peer
+/// that agreed still follows its members into later rounds, as one that
+/// missed its commit may leave it out, so that no two peers end up agreed
+/// on different members.
+#[derive(Debug)]
+pub struct Consensus {
+    pub mission: u64,
+    me: PeerId,
+    round: u32,
+    members: BTreeMap<PeerId, Member>,
+    commits: HashMap<PeerId, (u32, Vec<PeerId>)>, // Latest round and members of every peer.
+    round_started: Instant,
+    agreed: bool,
+}
+
+/// The members a mission was agreed between, in the order of their ids.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Agreement {
+    pub mission: u64,
+    pub members: Vec<Member>,
+}
+
+/// A mission agreed between peers, with the members that have failed and
+/// those that have finished their share.
+#[derive(Debug)]
+pub struct PeerMission {
+    pub agreement: Agreement,
+    pub area: Array2<u32>,
+    pub pattern: PatternId,
+    dead: BTreeSet<PeerId>,
+    finished: HashMap<PeerId, Vec<PeerId>>, // The dead every member had taken over from when it finished.
+    taken: Vec<(Coordinate, (usize, usize))>, // Areas this peer has searched or queued.
+}
+
+impl Coordination {
+    /// Coordination selected through the `D2D_COORDINATION` environment
+    /// variable, by the mothership if unset.
+    pub fn from_env() -> Result<Self, D2dError> {
+        config::from_env("D2D_COORDINATION")
+    }
+}
+
+impl FromStr for Coordination {
+    type Err = D2dError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mothership" => Ok(Coordination::Mothership),
+            "peer" => Ok(Coordination::Peer),
+            _ => Err(D2dError::InvalidConfig(format!("unknown coordination {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Coordination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Coordination::Mothership => f.write_str("mothership"),
+            Coordination::Peer => f.write_str("peer"),
+        }
+    }
+}
+
+/// Identifies a mission by its area and pattern, the same on every peer.
+pub fn mission_id(area: &Array2<u32>, pattern: PatternId) -> u64 {
+    // FNV-1a, which unlike the std hashers is the same on every build
+    let (rows, cols) = area.dim();
+    let bytes = [rows as u64, cols as u64]
+        .into_iter()
+        .flat_map(u64::to_le_bytes)
+        .chain(area.iter().flat_map(|cell| cell.to_le_bytes()))
+        .chain(pattern.to_string().into_bytes());
+    bytes.fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+impl Consensus {
+    pub fn new(mission: u64, me: Member, now: Instant) -> Self {
+        Consensus {
+            mission,
+            me: me.peer_id,
+            round: 0,
+            members: BTreeMap::from([(me.peer_id, me)]),
+            commits: HashMap::new(),
+            round_started: now,
+            agreed: false,
+        }
+    }
+
+    /// What this peer publishes in the current round.
+    pub fn commit(&self) -> Commit {
+        Commit { mission: self.mission, round: self.round, members: self.members.values().copied().collect() }
+    }
+
+    /// Takes in the commit of another peer.
+    pub fn receive(&mut self, from: PeerId, commit: Commit, now: Instant) {
+        if commit.mission != self.mission || self.is_excluded() {
+            return;
+        }
+        if commit.round > self.round.saturating_add(MAX_ROUND_LEAP) {
+            return;
+        }
+        // members with capabilities no minion has would break the split of the mission
+        if commit.members.iter().any(|m| !m.capabilities.is_valid()) {
+            return;
+        }
+        // while gathering, peers that have committed already are joined in their round
+        if self.round == 0 {
+            for member in &commit.members {
+                self.members.entry(member.peer_id).or_insert(*member);
+            }
+            if commit.round == 0 {
+                return;
+            }
+        }
+        // only the members of this peer can change its mind, and only in later rounds
+        if commit.round < self.round || !self.members.contains_key(&from) {
+            return;
+        }
+        let members = commit.members.iter().map(|m| m.peer_id).collect::<Vec<_>>();
+        if commit.round > self.round {
+            self.members.retain(|peer_id, _| members.contains(peer_id));
+            self.start_round(commit.round, now);
+            self.agreed = false;
+        }
+        self.commits.insert(from, (commit.round, members));
+        self.settle(now);
+    }
+
+    /// Closes the gathering and leaves out members that have been silent for
+    /// too long.
+    pub fn tick(&mut self, now: Instant) {
+        if self.agreed || self.is_excluded() || now.duration_since(self.round_started) < self.timeout() {
+            return;
+        }
+        if self.round > 0 {
+            let (me, round) = (self.me, self.round);
+            let commits = &self.commits;
+            self.members.retain(|peer_id, _| *peer_id == me || commits.get(peer_id).is_some_and(|(r, _)| *r == round));
+        }
+        self.start_round(self.round.saturating_add(1), now);
+        self.settle(now);
+    }
+
+    /// The members agreed on, once every one of them has committed to them.
+    /// It changes if a member moves on to a later round.
+    pub fn agreement(&self) -> Option<Agreement> {
+        self.agreed.then(|| Agreement { mission: self.mission, members: self.members.values().copied().collect() })
+    }
+
+    /// Whether the others went ahead without this peer.
+    pub fn is_excluded(&self) -> bool {
+        !self.members.contains_key(&self.me)
+    }
+
+    fn timeout(&self) -> Duration {
+        if self.round == 0 {
+            GATHER
+        } else {
+            SILENCE
+        }
+    }
+
+    fn start_round(&mut self, round: u32, now: Instant) {
+        self.round = round;
+        self.round_started = now;
+    }
+
+    // Agrees if every member committed to the same members this round, or
+    // starts a new round with the members they have in common.
+    fn settle(&mut self, now: Instant) {
+        if self.round == 0 {
+            return;
+        }
+        let members = self.members.keys().copied().collect::<Vec<_>>();
+        let commits = members
+            .iter()
+            .filter(|peer_id| **peer_id != self.me)
+            .map(|peer_id| self.commits.get(peer_id).filter(|(round, _)| *round == self.round).map(|(_, c)| c))
+            .collect::<Vec<_>>();
+
+        let disagreeing = commits.iter().flatten().filter(|c| **c != &members).copied().cloned().collect::<Vec<_>>();
+        if !disagreeing.is_empty() {
+            self.members.retain(|peer_id, _| disagreeing.iter().all(|c| c.contains(peer_id)));
+            self.start_round(self.round.saturating_add(1), now);
+        } else if commits.iter().all(Option::is_some) {
+            self.agreed = true;
+        }
+    }
+}
+
+impl Agreement {
+    pub fn is_member(&self, peer_id: &PeerId) -> bool {
+        self.members.iter().any(|m| m.peer_id == *peer_id)
+    }
+
+    /// Areas every living member searches once `dead` have failed. The
+    /// mission is split between all members as the mothership would, then
+    /// the share of every dead member, in the order of their ids, is split
+    /// between the living. It only depends on who is dead, not on the order
+    /// in which peers notice, so peers that know the same dead agree.
+    pub fn shares(
+        &self,
+        area: &Array2<u32>,
+        partitioner: &dyn AreaPartitioner,
+        dead: &BTreeSet<PeerId>,
+    ) -> BTreeMap<PeerId, Vec<(Coordinate, Array2<u32>)>> {
+        let capabilities = self.members.iter().map(|m| m.capabilities).collect::<Vec<_>>();
+        let participants = capability::shares(area.len(), &capabilities)
+            .into_iter()
+            .zip(&self.members)
+            .map(|(weight, member)| Participant { position: member.position, weight })
+            .collect::<Vec<_>>();
+        let regions = assign_regions(partitioner.partition(area, &participants), &participants, &capabilities);
+        let mut shares = self
+            .members
+            .iter()
+            .zip(regions)
+            .map(|(member, region)| {
+                let areas = region.into_iter().map(|([x, y], area)| (Coordinate { x, y }, area)).collect::<Vec<_>>();
+                (member.peer_id, areas)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let living = self.members.iter().filter(|m| !dead.contains(&m.peer_id)).collect::<Vec<_>>();
+        let capabilities = living.iter().map(|m| m.capabilities).collect::<Vec<_>>();
+        for peer_id in dead {
+            let orphaned = match shares.remove(peer_id) {
+                Some(areas) if !living.is_empty() => areas,
+                _ => continue,
+            };
+            let cells = orphaned.iter().map(|(_, area)| area.len()).sum();
+            let weights = capability::shares(cells, &capabilities);
+            let minions = living.iter().map(|m| m.peer_id).zip(weights).collect::<Vec<_>>();
+            for (minion, origin, area) in reassign(orphaned, &minions) {
+                shares.entry(minion).or_default().push((origin, area));
+            }
+        }
+        shares
+    }
+}
+
+impl PeerMission {
+    pub fn new(agreement: Agreement, area: Array2<u32>, pattern: PatternId) -> Self {
+        PeerMission { agreement, area, pattern, dead: BTreeSet::new(), finished: HashMap::new(), taken: Vec::new() }
+    }
+
+    pub fn mission(&self) -> u64 {
+        self.agreement.mission
+    }
+
+    /// Areas of the share of `me` that it has not taken on yet.
+    pub fn take(&mut self, me: PeerId, partitioner: &dyn AreaPartitioner) -> Vec<(Coordinate, Array2<u32>)> {
+        let mut share = self.agreement.shares(&self.area, partitioner, &self.dead).remove(&me).unwrap_or_default();
+        share.retain(|(origin, area)| {
+            let key = (*origin, area.dim());
+            mask::searchable_cells(area.view()) > 0 && !self.taken.contains(&key)
+        });
+        self.taken.extend(share.iter().map(|(origin, area)| (*origin, area.dim())));
+        share
+    }
+
+    /// Records that a member has failed, returning `false` if it is not a
+    /// member or was known to be dead.
+    pub fn fail(&mut self, peer_id: PeerId) -> bool {
+        self.agreement.is_member(&peer_id) && self.dead.insert(peer_id)
+    }
+
+    /// The members known to be dead, in the order of their ids.
+    pub fn dead(&self) -> Vec<PeerId> {
+        self.dead.iter().copied().collect()
+    }
+
+    /// Records that a member finished its share, having taken over from `dead`.
+    pub fn finish(&mut self, peer_id: PeerId, dead: Vec<PeerId>) {
+        if self.agreement.is_member(&peer_id) {
+            self.finished.insert(peer_id, dead);
+        }
+    }
+
+    /// Whether `peer_id` finished its share, including what it took over
+    /// from every member known to be dead.
+    pub fn has_finished(&self, peer_id: &PeerId) -> bool {
+        self.finished.get(peer_id).is_some_and(|dead| dead.iter().eq(self.dead.iter()))
+    }
+
+    /// Whether every living member has finished its share.
+    pub fn is_complete(&self) -> bool {
+        self.living().all(|peer_id| self.has_finished(&peer_id))
+    }
+
+    /// The living member that tells the operator the mission is complete.
+    pub fn reporter(&self) -> Option<PeerId> {
+        self.living().next()
+    }
+
+    fn living(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.agreement.members.iter().map(|m| m.peer_id).filter(|peer_id| !self.dead.contains(peer_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partition::PartitionerId;
+    use crate::NO_GO;
+
+    fn member(x: i32, y: i32) -> Member {
+        Member { peer_id: PeerId::random(), position: Coordinate { x, y }, capabilities: Capabilities::default() }
+    }
+
+    // Delivers every commit to every other peer until nothing changes.
+    fn exchange(peers: &mut [Consensus], now: Instant) {
+        for _ in 0..peers.len() + 2 {
+            let commits = peers.iter().map(|p| (p.me, p.commit())).collect::<Vec<_>>();
+            for peer in peers.iter_mut() {
+                for (from, commit) in &commits {
+                    if *from != peer.me {
+                        peer.receive(*from, commit.clone(), now);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn peers_agree_on_who_takes_part() {
+        let now = Instant::now();
+        let members = [member(0, 0), member(0, 9), member(9, 0)];
+        let mut peers = members.iter().map(|m| Consensus::new(7, *m, now)).collect::<Vec<_>>();
+        exchange(&mut peers, now);
+        assert!(peers.iter().all(|p| p.agreement().is_none()));
+
+        // the gathering closes, everyone commits to the three of them
+        let later = now + GATHER;
+        peers.iter_mut().for_each(|p| p.tick(later));
+        exchange(&mut peers, later);
+        let agreement = peers[0].agreement().unwrap();
+        assert_eq!(agreement.members.len(), 3);
+        assert!(agreement.members.windows(2).all(|m| m[0].peer_id < m[1].peer_id));
+        assert!(peers.iter().all(|p| p.agreement().as_ref() == Some(&agreement)));
+    }
+
+    #[test]
+    fn late_and_silent_peers_are_left_out() {
+        let now = Instant::now();
+        let later = now + GATHER;
+        let (first, second, late) = (member(0, 0), member(5, 5), member(9, 9));
+        let mut peers = vec![Consensus::new(7, first, now), Consensus::new(7, second, now)];
+        exchange(&mut peers, now);
+        peers.iter_mut().for_each(|p| p.tick(later));
+
+        // heard of by the second peer only, after the first had closed its gathering
+        let mut latecomer = Consensus::new(7, late, now);
+        peers[1].members.insert(late.peer_id, late);
+        latecomer.receive(peers[1].me, peers[1].commit(), later);
+        latecomer.tick(later);
+        peers.push(latecomer);
+        exchange(&mut peers, later);
+
+        let mut agreed = vec![first, second];
+        agreed.sort_by_key(|m| m.peer_id);
+        assert_eq!(peers[0].agreement().map(|a| a.members), Some(agreed));
+        assert_eq!(peers[0].agreement(), peers[1].agreement());
+        assert!(peers[2].is_excluded());
+
+        // a peer that stops committing is left out after a while
+        let mut alone = Consensus::new(8, first, now);
+        alone.receive(second.peer_id, Commit { mission: 8, round: 0, members: vec![second] }, now);
+        alone.tick(later);
+        assert!(alone.agreement().is_none());
+        alone.tick(later + SILENCE);
+        assert_eq!(alone.agreement().unwrap().members, vec![first]);
+    }
+
+    #[test]
+    fn agreed_peers_follow_later_rounds() {
+        let now = Instant::now();
+        let later = now + GATHER;
+        let members = [member(0, 0), member(0, 9), member(9, 0)];
+        let mut peers = members.iter().map(|m| Consensus::new(7, *m, now)).collect::<Vec<_>>();
+        exchange(&mut peers, now);
+        peers.iter_mut().for_each(|p| p.tick(later));
+
+        // the third misses the first one's commit, which the other two agree without
+        let commits = peers.iter().map(|p| (p.me, p.commit())).collect::<Vec<_>>();
+        for (to, peer) in peers.iter_mut().enumerate() {
+            for (from, (peer_id, commit)) in commits.iter().enumerate() {
+                if from != to && (from, to) != (0, 2) {
+                    peer.receive(*peer_id, commit.clone(), later);
+                }
+            }
+        }
+        assert!(peers[0].agreement().is_some() && peers[1].agreement().is_some());
+        assert!(peers[2].agreement().is_none());
+
+        // so the third leaves it out, and the agreed peers follow
+        peers[2].tick(later + SILENCE);
+        exchange(&mut peers, later + SILENCE);
+        assert!(peers[0].is_excluded() && peers[0].agreement().is_none());
+        let mut agreed = vec![members[1], members[2]];
+        agreed.sort_by_key(|m| m.peer_id);
+        assert_eq!(peers[1].agreement().map(|a| a.members), Some(agreed));
+        assert_eq!(peers[1].agreement(), peers[2].agreement());
+    }
+
+    #[test]
+    fn commits_far_ahead_are_ignored() {
+        let now = Instant::now();
+        let (first, second) = (member(0, 0), member(5, 5));
+        let mut peer = Consensus::new(7, first, now);
+        peer.receive(second.peer_id, Commit { mission: 7, round: 0, members: vec![first, second] }, now);
+        peer.tick(now + GATHER);
+
+        let forged = Commit { mission: 7, round: u32::MAX, members: vec![second] };
+        peer.receive(second.peer_id, forged, now + GATHER);
+        assert_eq!(peer.commit().round, 1);
+        assert!(!peer.is_excluded());
+
+        // as are members that could not search
+        let broken = Member { capabilities: Capabilities { speed: f64::NAN, ..second.capabilities }, ..second };
+        peer.receive(second.peer_id, Commit { mission: 7, round: 2, members: vec![first, broken] }, now + GATHER);
+        assert_eq!(peer.commit().round, 1);
+
+        // a peer a few rounds ahead is still followed
+        let mut members = vec![first, second];
+        members.sort_by_key(|m| m.peer_id);
+        peer.receive(second.peer_id, Commit { mission: 7, round: 3, members }, now + GATHER);
+        assert_eq!(peer.commit().round, 3);
+        assert!(peer.agreement().is_some());
+    }
+
+    #[test]
+    fn shares_cover_the_mission_whoever_dies() {
+        let mut area = Array2::<u32>::zeros((12, 10));
+        area[[3, 3]] = NO_GO;
+        let mut members = vec![member(0, 0), member(11, 9), member(0, 9), member(11, 0)];
+        members.sort_by_key(|m| m.peer_id);
+        let agreement = Agreement { mission: 1, members: members.clone() };
+        let partitioner = PartitionerId::Tiles.partitioner();
+
+        let mut dead = BTreeSet::new();
+        for failed in [members[2].peer_id, members[0].peer_id] {
+            dead.insert(failed);
+            let shares = agreement.shares(&area, partitioner.as_ref(), &dead);
+            assert!(shares.keys().all(|peer_id| !dead.contains(peer_id)));
+
+            let mut covered = Array2::<u32>::zeros(area.dim());
+            for (origin, piece) in shares.values().flatten() {
+                let (rows, cols) = piece.dim();
+                for (i, j) in (0..rows).flat_map(|i| (0..cols).map(move |j| (i, j))) {
+                    covered[[origin.x as usize + i, origin.y as usize + j]] += 1;
+                }
+            }
+            assert!(covered.iter().all(|&c| c == 1), "{}", covered);
+        }
+    }
+
+    #[test]
+    fn mission_completes_once_the_living_finish() {
+        let members = [member(0, 0), member(0, 5)];
+        let (first, second) = (members[0].peer_id.min(members[1].peer_id), members[0].peer_id.max(members[1].peer_id));
+        let agreement = Agreement { mission: 1, members: members.to_vec() };
+        let area = Array2::<u32>::zeros((4, 6));
+        let mut mission = PeerMission::new(agreement, area, PatternId::default());
+        let partitioner = PartitionerId::Strips.partitioner();
+
+        let before = mission.take(first, partitioner.as_ref());
+        assert!(!before.is_empty());
+        mission.finish(first, mission.dead());
+        assert!(!mission.is_complete());
+
+        // the second fails, the first takes over its share and has to finish again
+        assert!(mission.fail(second) && !mission.fail(second));
+        assert!(!mission.fail(PeerId::random()));
+        let cells = mission.take(first, partitioner.as_ref()).iter().map(|(_, a)| a.len()).sum::<usize>();
+        assert_eq!(cells, 12);
+        assert!(mission.take(first, partitioner.as_ref()).is_empty());
+        assert!(!mission.is_complete());
+        mission.finish(first, mission.dead());
+        assert!(mission.is_complete());
+        assert_eq!(mission.reporter(), Some(first));
+    }
+
+    #[test]
+    fn mission_ids() {
+        let area = Array2::<u32>::zeros((4, 6));
+        let id = mission_id(&area, PatternId::Sweep);
+        assert_eq!(id, mission_id(&area.clone(), PatternId::Sweep));
+        assert_ne!(id, mission_id(&area, PatternId::Spiral));
+        assert_ne!(id, mission_id(&Array2::zeros((6, 4)), PatternId::Sweep));
+    }
+
+    #[test]
+    fn coordination_names() {
+        config::assert_names([Coordination::Mothership, Coordination::Peer], "swarm");
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Coordinate;
+
+/// Direction of the lanes of a sweep.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Lanes {
+    #[default]
+    Rows,
+    Columns,
+}
+
+/// How a minion sweeps its area: lane by lane, turning around at the end of
+/// every lane rather than flying back to where it started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Sweep {
+    pub lanes: Lanes,
+    pub last_lane_first: bool, // Start at the last row or column rather than the first.
+    pub backwards: bool,       // Search the first lane towards its start.
+}
+
+impl Sweep {
+    /// Sweep of an area with lanes along its longest axis, for the fewest
+    /// turns, starting at the corner nearest to `start`, relative to the area.
+    pub fn plan((rows, cols): (usize, usize), start: Coordinate) -> Self {
+        let lanes = if cols >= rows { Lanes::Rows } else { Lanes::Columns };
+        let far_row = (start.x as f64) > (rows as f64 - 1.0) / 2.0;
+        let far_col = (start.y as f64) > (cols as f64 - 1.0) / 2.0;
+        match lanes {
+            Lanes::Rows => Sweep { lanes, last_lane_first: far_row, backwards: far_col },
+            Lanes::Columns => Sweep { lanes, last_lane_first: far_col, backwards: far_row },
+        }
+    }
+
+    /// Every cell of a `rows x cols` area in the order they are swept.
+    pub fn path(&self, (rows, cols): (usize, usize)) -> Vec<(usize, usize)> {
+        let (lanes, length) = match self.lanes {
+            Lanes::Rows => (rows, cols),
+            Lanes::Columns => (cols, rows),
+        };
+        let mut path = Vec::with_capacity(rows * cols);
+        for n in 0..lanes {
+            let lane = if self.last_lane_first { lanes - 1 - n } else { n };
+            // every other lane is searched backwards
+            let backwards = self.backwards != (n % 2 == 1);
+            for m in 0..length {
+                let step = if backwards { length - 1 - m } else { m };
+                path.push(match self.lanes {
+                    Lanes::Rows => (lane, step),
+                    Lanes::Columns => (step, lane),
+                });
+            }
+        }
+        path
+    }
+}
+
+/// Distance flown along `path`, from cell centre to cell centre.
+pub fn path_length(path: &[(usize, usize)]) -> f64 {
+    path.windows(2)
+        .map(|pair| {
+            let (dx, dy) = (pair[0].0 as f64 - pair[1].0 as f64, pair[0].1 as f64 - pair[1].1 as f64);
+            (dx * dx + dy * dy).sqrt()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_major((rows, cols): (usize, usize)) -> Vec<(usize, usize)> {
+        (0..rows).flat_map(|i| (0..cols).map(move |j| (i, j))).collect()
+    }
+
+    #[test]
+    fn serpentine_paths() {
+        let path = Sweep::default().path((3, 3));
+        assert_eq!(path, vec![(0, 0), (0, 1), (0, 2), (1, 2), (1, 1), (1, 0), (2, 0), (2, 1), (2, 2)]);
+
+        let sweep = Sweep { lanes: Lanes::Columns, last_lane_first: true, backwards: true };
+        assert_eq!(sweep.path((2, 3)), vec![(1, 2), (0, 2), (0, 1), (1, 1), (1, 0), (0, 0)]);
+
+        // every variation visits every cell once, one step at a time
+        for lanes in [Lanes::Rows, Lanes::Columns] {
+            for (last_lane_first, backwards) in [(false, false), (false, true), (true, false), (true, true)] {
+                let mut path = Sweep { lanes, last_lane_first, backwards }.path((4, 7));
+                assert_eq!(path_length(&path), 27.0);
+                path.sort();
+                assert_eq!(path, row_major((4, 7)));
+            }
+        }
+    }
+
+    #[test]
+    fn shorter_than_row_major() {
+        for dim in [(2, 2), (5, 8), (16, 24), (40, 3)] {
+            let serpentine = path_length(&Sweep::plan(dim, Coordinate { x: 0, y: 0 }).path(dim));
+            let row_major = path_length(&row_major(dim));
+            assert_eq!(serpentine, (dim.0 * dim.1 - 1) as f64);
+            assert!(serpentine < row_major, "{:?}: {} >= {}", dim, serpentine, row_major);
+        }
+        // 16 rows of 24 cells: 23 cells along each row, then back across 23 cells and one row down
+        let row_major = path_length(&row_major((16, 24)));
+        assert!((row_major - (16.0 * 23.0 + 15.0 * (23f64 * 23.0 + 1.0).sqrt())).abs() < 1e-9);
+        assert_eq!(path_length(&Sweep::default().path((1, 1))), 0.0);
+    }
+
+    #[test]
+    fn plans_from_the_nearest_corner() {
+        let sweep = Sweep::plan((4, 10), Coordinate { x: 5, y: -2 });
+        assert_eq!(sweep, Sweep { lanes: Lanes::Rows, last_lane_first: true, backwards: false });
+        assert_eq!(sweep.path((4, 10))[0], (3, 0));
+
+        let sweep = Sweep::plan((10, 4), Coordinate { x: 9, y: 3 });
+        assert_eq!(sweep.lanes, Lanes::Columns);
+        assert_eq!(sweep.path((10, 4))[0], (9, 3));
+    }
+}
@@ -0,0 +1,199 @@
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Exponential backoff between delivery attempts.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub max_attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_secs(2),
+            max: Duration::from_secs(30),
+            max_attempts: 8,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the attempt following attempt number `attempt`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// Starting point for ids that only need to be unique per sender, also
+/// across restarts.
+pub fn seed_id() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+struct Pending<T> {
+    peer_id: PeerId,
+    message: T,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+/// What the outbox wants done on a tick.
+#[derive(Debug, PartialEq)]
+pub enum Delivery<T> {
+    Resend { id: u64, peer_id: PeerId, message: T },
+    GaveUp { id: u64, peer_id: PeerId, message: T },
+}
+
+/// Messages that have been sent but not yet acknowledged, keyed by an id the
+/// receiver uses to recognise repeated deliveries. Resending is driven by
+/// [`Outbox::due`], so a reply lost in transit only costs a duplicate.
+#[derive(Debug)]
+pub struct Outbox<T> {
+    pending: HashMap<u64, Pending<T>>,
+    pub backoff: Backoff,
+}
+
+impl<T> Default for Outbox<T> {
+    fn default() -> Self {
+        Outbox {
+            pending: HashMap::new(),
+            backoff: Backoff::default(),
+        }
+    }
+}
+
+impl<T: Clone> Outbox<T> {
+    /// Records a message that was just sent for the first time.
+    pub fn insert(&mut self, id: u64, peer_id: PeerId, message: T, now: Instant) {
+        let next_attempt = now + self.backoff.delay(1);
+        self.pending.insert(
+            id,
+            Pending {
+                peer_id,
+                message,
+                attempts: 1,
+                next_attempt,
+            },
+        );
+    }
+
+    pub fn ack(&mut self, id: u64) -> Option<T> {
+        self.pending.remove(&id).map(|p| p.message)
+    }
+
+    pub fn is_pending(&self, id: u64) -> bool {
+        self.pending.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Messages whose acknowledgement is overdue. Each is either to be sent
+    /// again, or dropped once it has used up its attempts.
+    pub fn due(&mut self, now: Instant) -> Vec<Delivery<T>> {
+        let mut deliveries = Vec::new();
+        let mut gave_up = Vec::new();
+
+        for (&id, pending) in self.pending.iter_mut() {
+            if now < pending.next_attempt {
+                continue;
+            }
+            if pending.attempts >= self.backoff.max_attempts {
+                gave_up.push(id);
+                continue;
+            }
+            pending.attempts += 1;
+            pending.next_attempt = now + self.backoff.delay(pending.attempts);
+            deliveries.push(Delivery::Resend {
+                id,
+                peer_id: pending.peer_id,
+                message: pending.message.clone(),
+            });
+        }
+
+        for id in gave_up {
+            let pending = self.pending.remove(&id).unwrap();
+            deliveries.push(Delivery::GaveUp {
+                id,
+                peer_id: pending.peer_id,
+                message: pending.message,
+            });
+        }
+        deliveries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            max_attempts: 5,
+        };
+        let delays = (1..=6).map(|a| backoff.delay(a).as_secs()).collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(backoff.delay(100), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn retries_until_acknowledged() {
+        let peer_id = PeerId::random();
+        let mut outbox = Outbox::default();
+        let start = Instant::now();
+        outbox.insert(7, peer_id, "task", start);
+
+        assert!(outbox.due(start).is_empty());
+        let retry = start + outbox.backoff.delay(1);
+        assert_eq!(
+            outbox.due(retry),
+            vec![Delivery::Resend { id: 7, peer_id, message: "task" }]
+        );
+        // the next attempt waits twice as long
+        assert!(outbox.due(retry + outbox.backoff.delay(1)).is_empty());
+
+        assert_eq!(outbox.ack(7), Some("task"));
+        assert_eq!(outbox.ack(7), None);
+        assert!(outbox.due(retry + outbox.backoff.max).is_empty());
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let peer_id = PeerId::random();
+        let mut outbox = Outbox::default();
+        outbox.backoff.max_attempts = 3;
+        let mut now = Instant::now();
+        outbox.insert(1, peer_id, (), now);
+
+        let mut resends = 0;
+        loop {
+            now += outbox.backoff.max;
+            match outbox.due(now).pop() {
+                Some(Delivery::Resend { .. }) => resends += 1,
+                Some(Delivery::GaveUp { id, .. }) => {
+                    assert_eq!(id, 1);
+                    break;
+                }
+                None => panic!("nothing due"),
+            }
+        }
+        assert_eq!(resends, 2);
+        assert!(outbox.is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use libp2p::gossipsub::error::PublishError;
+use std::error::Error;
+use std::fmt;
+
+use crate::version::ProtocolVersion;
+
+/// Errors raised while encoding or decoding protocol messages, or while
+/// reading a node's configuration.
+#[derive(Debug)]
+pub enum D2dError {
+    Serialization(Box<dyn Error + Send + Sync>),
+    UnsupportedVersion(ProtocolVersion),
+    UnknownCodec(u8),
+    UnknownCodecName(String),
+    InvalidConfig(String),
+    EmptyFrame,
+    CorruptGrid(String),
+    CorruptChunk(u64),
+    FrameTooLarge(usize),
+    Publish(PublishError),
+}
+
+impl fmt::Display for D2dError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            D2dError::Serialization(e) => write!(f, "malformed message: {}", e),
+            D2dError::UnsupportedVersion(v) => write!(f, "unsupported protocol version {}", v),
+            D2dError::UnknownCodec(id) => write!(f, "unknown codec identifier {:#04x}", id),
+            D2dError::UnknownCodecName(name) => write!(f, "unknown codec {:?}", name),
+            D2dError::InvalidConfig(reason) => write!(f, "invalid configuration: {}", reason),
+            D2dError::EmptyFrame => write!(f, "empty frame"),
+            D2dError::CorruptGrid(reason) => write!(f, "corrupt grid: {}", reason),
+            D2dError::CorruptChunk(id) => write!(f, "corrupt chunk in transfer {}", id),
+            D2dError::FrameTooLarge(len) => write!(f, "frame of {} bytes is too large to send", len),
+            D2dError::Publish(e) => write!(f, "publish failed: {}", e),
+        }
+    }
+}
+
+impl Error for D2dError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            D2dError::Serialization(e) => Some(e.as_ref()),
+            D2dError::Publish(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for D2dError {
+    fn from(e: serde_json::Error) -> Self {
+        D2dError::Serialization(Box::new(e))
+    }
+}
+
+impl From<serde_cbor::Error> for D2dError {
+    fn from(e: serde_cbor::Error) -> Self {
+        D2dError::Serialization(Box::new(e))
+    }
+}
+
+impl From<bincode::Error> for D2dError {
+    fn from(e: bincode::Error) -> Self {
+        D2dError::Serialization(e)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for D2dError {
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        D2dError::Serialization(Box::new(e))
+    }
+}
+
+impl From<rmp_serde::decode::Error> for D2dError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        D2dError::Serialization(Box::new(e))
+    }
+}
+
+impl From<PublishError> for D2dError {
+    fn from(e: PublishError) -> Self {
+        D2dError::Publish(e)
+    }
+}
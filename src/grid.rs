@@ -0,0 +1,325 @@
+use ndarray::Array2;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+use crate::mission::MAX_CELLS;
+use crate::D2dError;
+
+/// Compact representation of a mission area. Areas are usually sparse 0/1
+/// grids, which pack into a bitset or a short list of runs; grids with other
+/// values fall back to run-length or plain varint encoding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CompressedGrid {
+    Bitset { shape: [usize; 2], bits: Bytes },
+    RunLength { shape: [usize; 2], runs: Bytes },
+    Dense { shape: [usize; 2], values: Bytes },
+}
+
+// Byte buffer serialized as base64 in human readable formats and as raw bytes
+// everywhere else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bytes(pub Vec<u8>);
+
+impl CompressedGrid {
+    /// Picks the smallest encoding for `area`.
+    pub fn compress(area: &Array2<u32>) -> Self {
+        let shape = [area.nrows(), area.ncols()];
+
+        let mut runs = Vec::new();
+        let mut cells = area.iter().copied().peekable();
+        while let Some(value) = cells.next() {
+            let mut length = 1u64;
+            while cells.peek() == Some(&value) {
+                cells.next();
+                length += 1;
+            }
+            write_varint(&mut runs, value as u64);
+            write_varint(&mut runs, length);
+        }
+        let mut best = CompressedGrid::RunLength { shape, runs: Bytes(runs) };
+
+        if area.iter().all(|&v| v <= 1) {
+            let mut bits = vec![0u8; area.len().div_ceil(8)];
+            for (i, &v) in area.iter().enumerate() {
+                bits[i / 8] |= (v as u8) << (i % 8);
+            }
+            if bits.len() < best.encoded_len() {
+                best = CompressedGrid::Bitset { shape, bits: Bytes(bits) };
+            }
+        } else {
+            let mut values = Vec::new();
+            for &v in area.iter() {
+                write_varint(&mut values, v as u64);
+            }
+            if values.len() < best.encoded_len() {
+                best = CompressedGrid::Dense { shape, values: Bytes(values) };
+            }
+        }
+
+        best
+    }
+
+    pub fn decompress(&self) -> Result<Array2<u32>, D2dError> {
+        let shape = self.shape();
+        let len = shape[0]
+            .checked_mul(shape[1])
+            .ok_or_else(|| corrupt("grid shape overflows"))?;
+        if len > MAX_CELLS {
+            return Err(corrupt("grid larger than any mission"));
+        }
+
+        // the shape comes off the wire, so the payload has to account for
+        // every cell before anything is allocated for them
+        let runs = match self {
+            CompressedGrid::Bitset { bits, .. } => {
+                if bits.0.len() != len.div_ceil(8) {
+                    return Err(corrupt("bitset length does not match shape"));
+                }
+                None
+            }
+            CompressedGrid::RunLength { runs, .. } => {
+                let mut data = runs.0.as_slice();
+                let mut decoded = Vec::new();
+                let mut total = 0usize;
+                while !data.is_empty() {
+                    let value = read_u32(&mut data)?;
+                    let length = read_varint(&mut data)?;
+                    total = usize::try_from(length)
+                        .ok()
+                        .and_then(|length| total.checked_add(length))
+                        .filter(|&total| total <= len)
+                        .ok_or_else(|| corrupt("runs exceed grid size"))?;
+                    decoded.push((value, total));
+                }
+                if total != len {
+                    return Err(corrupt("runs do not cover the grid"));
+                }
+                Some(decoded)
+            }
+            CompressedGrid::Dense { values, .. } => {
+                // every varint ends in the one byte without a continuation bit
+                if values.0.iter().filter(|&&byte| byte & 0x80 == 0).count() != len {
+                    return Err(corrupt("value count does not match shape"));
+                }
+                None
+            }
+        };
+
+        let mut cells = Vec::with_capacity(len);
+        match self {
+            CompressedGrid::Bitset { bits, .. } => {
+                cells.extend((0..len).map(|i| ((bits.0[i / 8] >> (i % 8)) & 1) as u32));
+            }
+            CompressedGrid::RunLength { .. } => {
+                for (value, end) in runs.unwrap_or_default() {
+                    cells.resize(end, value);
+                }
+            }
+            CompressedGrid::Dense { values, .. } => {
+                let mut data = values.0.as_slice();
+                while !data.is_empty() {
+                    cells.push(read_u32(&mut data)?);
+                }
+            }
+        }
+
+        Array2::from_shape_vec((shape[0], shape[1]), cells)
+            .map_err(|_| corrupt("cell count does not match shape"))
+    }
+
+    pub fn shape(&self) -> [usize; 2] {
+        match self {
+            CompressedGrid::Bitset { shape, .. }
+            | CompressedGrid::RunLength { shape, .. }
+            | CompressedGrid::Dense { shape, .. } => *shape,
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            CompressedGrid::Bitset { bits: b, .. }
+            | CompressedGrid::RunLength { runs: b, .. }
+            | CompressedGrid::Dense { values: b, .. } => b.0.len(),
+        }
+    }
+}
+
+fn corrupt(reason: &str) -> D2dError {
+    D2dError::CorruptGrid(reason.to_string())
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> Result<u64, D2dError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first().ok_or_else(|| corrupt("truncated varint"))?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(corrupt("varint too long"))
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<u32, D2dError> {
+    u32::try_from(read_varint(data)?).map_err(|_| corrupt("cell value out of range"))
+}
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::encode(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = Bytes;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a byte buffer or base64 string")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Bytes, E> {
+                base64::decode(v).map(Bytes).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Bytes, E> {
+                Ok(Bytes(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Bytes, E> {
+                Ok(Bytes(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bytes, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(Bytes(bytes))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BytesVisitor)
+        } else {
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+}
+
+/// Serializes an `Array2<u32>` field as a [`CompressedGrid`], for use with
+/// `#[serde(with = "crate::grid::compressed")]`.
+pub mod compressed {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(area: &Array2<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        CompressedGrid::compress(area).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Array2<u32>, D::Error> {
+        CompressedGrid::deserialize(deserializer)?
+            .decompress()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{D2dMessage, PatternId, PROTOCOL_VERSION};
+
+    #[test]
+    fn picks_encoding() {
+        let mut sparse = Array2::<u32>::zeros((40, 40));
+        sparse[[3, 7]] = 1;
+        let compressed = CompressedGrid::compress(&sparse);
+        assert!(matches!(compressed, CompressedGrid::RunLength { .. }));
+        assert_eq!(compressed.decompress().unwrap(), sparse);
+
+        let checkered = Array2::from_shape_fn((9, 7), |(i, j)| ((i + j) % 2) as u32);
+        let compressed = CompressedGrid::compress(&checkered);
+        assert!(matches!(compressed, CompressedGrid::Bitset { .. }));
+        assert_eq!(compressed.decompress().unwrap(), checkered);
+
+        let noisy = Array2::from_shape_fn((5, 6), |(i, j)| (i * 6 + j) as u32 * 1000);
+        let compressed = CompressedGrid::compress(&noisy);
+        assert!(matches!(compressed, CompressedGrid::Dense { .. }));
+        assert_eq!(compressed.decompress().unwrap(), noisy);
+
+        let empty = Array2::<u32>::zeros((0, 4));
+        assert_eq!(CompressedGrid::compress(&empty).decompress().unwrap(), empty);
+    }
+
+    #[test]
+    fn rejects_corrupt_grids() {
+        let short = CompressedGrid::Bitset { shape: [4, 4], bits: Bytes(vec![0xff]) };
+        assert!(matches!(short.decompress(), Err(D2dError::CorruptGrid(_))));
+
+        let mut runs = Vec::new();
+        write_varint(&mut runs, 1);
+        write_varint(&mut runs, 17);
+        let long = CompressedGrid::RunLength { shape: [4, 4], runs: Bytes(runs) };
+        assert!(matches!(long.decompress(), Err(D2dError::CorruptGrid(_))));
+
+        let truncated = CompressedGrid::Dense { shape: [1, 1], values: Bytes(vec![0x80]) };
+        assert!(matches!(truncated.decompress(), Err(D2dError::CorruptGrid(_))));
+
+        let mut runs = Vec::new();
+        write_varint(&mut runs, 0);
+        write_varint(&mut runs, 3);
+        let short = CompressedGrid::RunLength { shape: [2, 2], runs: Bytes(runs) };
+        assert!(matches!(short.decompress(), Err(D2dError::CorruptGrid(_))));
+    }
+
+    #[test]
+    fn rejects_oversized_shapes() {
+        let huge = [4_000_000_000, 4_000_000_000];
+        for grid in [
+            CompressedGrid::Bitset { shape: huge, bits: Bytes(Vec::new()) },
+            CompressedGrid::RunLength { shape: [1 << 20, 1 << 20], runs: Bytes(Vec::new()) },
+            CompressedGrid::Dense { shape: [4096, 4096], values: Bytes(vec![0]) },
+        ] {
+            assert!(matches!(grid.decompress(), Err(D2dError::CorruptGrid(_))), "{:?}", grid.shape());
+        }
+
+        // a forged mission only fails to decode
+        let frame = format!(
+            r#"{{"version":{},"minor":{},"message":{{"NewMission":{{"area":{{"Bitset":{{"shape":[4000000000,4000000000],"bits":""}}}},"pattern":"Sweep"}}}}}}"#,
+            PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor,
+        );
+        match D2dMessage::decode(frame.as_bytes()) {
+            Err(D2dError::Serialization(e)) => assert!(e.to_string().contains("larger than any mission"), "{}", e),
+            other => panic!("decoded {:?}", other),
+        }
+    }
+
+    #[test]
+    fn large_sparse_mission_fits_gossipsub() {
+        let mut area = Array2::<u32>::zeros((1000, 1000));
+        for i in 0..1000 {
+            area[[(i * 7919) % 1000, (i * 104729) % 1000]] = 1;
+        }
+        let message = D2dMessage::NewMission { area, pattern: PatternId::default() };
+        let encoded = message.encode().unwrap();
+        // gossipsub's default max_transmit_size
+        assert!(encoded.len() < 65536, "{} bytes", encoded.len());
+        assert_eq!(D2dMessage::decode(&encoded).unwrap(), message);
+    }
+}
@@ -1,4 +1,6 @@
+use async_std::channel::{unbounded, Receiver};
 use async_std::stream::Stream;
+use async_std::task;
 use core::pin::Pin;
 use futures::task::Context;
 use futures::task::Poll;
@@ -6,7 +8,7 @@ use futures::task::Waker;
 use libp2p::PeerId;
 use ndarray::{concatenate, Array2, Axis};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::collections::VecDeque;
 use std::ops::Add;
 use std::sync::{Arc, Mutex};
@@ -14,13 +16,61 @@ use std::thread;
 use std::time::Duration;
 use std::vec::IntoIter;
 
+pub mod acting;
+pub mod assignment;
+pub mod auction;
+pub mod capability;
+pub mod chunk;
+pub mod codec;
+pub mod config;
+pub mod consensus;
+pub mod coverage;
+pub mod delivery;
+mod error;
+pub mod grid;
+pub mod liveness;
+pub mod mask;
+pub mod message;
+pub mod messenger;
+pub mod mission;
+pub mod network;
+pub mod partition;
+pub mod pattern;
+pub mod progress;
+pub mod routing;
+pub mod tour;
+pub mod transit;
+pub mod version;
+
+pub use capability::Capabilities;
+pub use codec::{Codec, CodecId};
+pub use coverage::{Lanes, Sweep};
+pub use delivery::{Backoff, Delivery, Outbox};
+pub use error::D2dError;
+pub use grid::CompressedGrid;
+pub use liveness::{FailureDetector, MinionStatus};
+pub use message::D2dMessage;
+pub use messenger::Messenger;
+pub use mission::{MissionDefinition, Point, Polygon, Raster};
+pub use network::{D2dBehaviour, D2dEvent};
+pub use mask::NO_GO;
+pub use partition::{AreaPartitioner, Participant, PartitionerId, Region};
+pub use pattern::{PatternId, SearchPattern};
+pub use progress::TaskProgress;
+pub use tour::{Tour, TourPlanner, TourPlannerId};
+pub use transit::{Route, TransitMap};
+pub use version::{Compatibility, PeerVersions, ProtocolVersion, Role, PROTOCOL_VERSION};
+
 #[derive(Debug)]
 pub struct MothershipState {
     pub position: Coordinate,
     pub mission_status: MissionStatus,
     pub mission_area: Option<Array2<u32>>,
+    pub search_pattern: PatternId, // Chosen by the operator for the mission in progress.
     pub delegate_tasks: DelegateTasks,
     pub points_of_interest: VecDeque<Coordinate>,
+    pub peer_versions: PeerVersions,
+    pub liveness: FailureDetector,
 }
 
 #[derive(Debug)]
@@ -31,16 +81,28 @@ pub struct MinionState {
     pub local_position: Coordinate,
     pub area_exhausted: bool,
     pub poi: bool,
+    pub transit: VecDeque<Coordinate>, // Cells to fly through before searching, relative to `global_position`.
+    pub in_transit: bool,
     pub mission_area: Option<IntoIter<((i32, i32), u32)>>,
     pub waker: Option<Waker>,
 }
 
-#[derive(Debug)]
+// Published by minions on the "heartbeat" topic, with the position in global coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MinionHeartbeat {
     pub position: Coordinate,
     pub poi: bool,
 }
 
+#[derive(Debug)]
+pub enum MinionEvent {
+    Searched(MinionHeartbeat),
+    // On the way to the current task's area, at a position relative to it.
+    Moved(Coordinate),
+    // The current task's area has been searched; the minion is ready for the next one.
+    AreaExhausted,
+}
+
 #[derive(Debug)]
 pub struct MinionStream {
     shared_state: Arc<Mutex<MinionState>>,
@@ -50,10 +112,12 @@ pub struct MinionStream {
 pub enum MissionStatus {
     Pending,
     InProgress,
+    // Searched, with points of interest still to be acted on.
+    Acting,
     Complete,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Coordinate {
     pub x: i32,
     pub y: i32,
@@ -66,18 +130,27 @@ pub struct Minion {
     pub position: Coordinate,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct DelegateTasks {
     pub minions: HashMap<PeerId, Coordinate>,
-    pub total: u32, // This is set once the mission is received, based on the number of subscribed minions.
+    pub total: u32, // Number of tasks the mission is currently split into.
     pub complete: u32,
+    pub completed: HashSet<u64>, // Ids of completed tasks, so that repeated completions are only counted once.
+    pub tasks: HashMap<u64, TaskProgress>, // Search progress of every task that is part of the mission.
+    pub unassigned: Vec<(Coordinate, Array2<u32>)>, // Areas withdrawn while no minion was available to take them.
+    pub stealing: HashMap<u64, PeerId>, // Tasks being split for an idle minion, with that minion.
+    pub capabilities: HashMap<PeerId, Capabilities>, // As advertised, minions that have not are assumed to have the defaults.
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DelegateTaskMessage {
+    pub task_id: u64,
     pub peer_id: PeerId,
     pub global_coordinates: Coordinate,
+    #[serde(with = "grid::compressed")]
     pub area: Array2<u32>,
+    pub pattern: PatternId, // Order the area is searched in, which cells are released in too,
+    pub start: Coordinate,  // from where, relative to the area.
 }
 
 impl Add for Coordinate {
@@ -91,6 +164,134 @@ impl Add for Coordinate {
     }
 }
 
+impl DelegateTasks {
+    pub fn assign(&mut self, task: &DelegateTaskMessage) {
+        self.tasks.insert(task.task_id, TaskProgress::new(task));
+        self.total += 1;
+    }
+
+    /// Counts a completed task, returning `false` if it was already counted
+    /// or has been withdrawn.
+    pub fn complete_task(&mut self, task_id: u64) -> bool {
+        if !self.tasks.contains_key(&task_id) || !self.completed.insert(task_id) {
+            return false;
+        }
+        self.complete += 1;
+        true
+    }
+
+    /// How many of `cells` cells each of `minions` should search, given their
+    /// capabilities.
+    pub fn weights(&self, minions: &[PeerId], cells: usize) -> Vec<f64> {
+        capability::shares(cells, &self.capabilities_of(minions))
+    }
+
+    /// Capabilities of `minions`, in their order.
+    pub fn capabilities_of(&self, minions: &[PeerId]) -> Vec<Capabilities> {
+        minions
+            .iter()
+            .map(|peer_id| self.capabilities.get(peer_id).copied().unwrap_or_default())
+            .collect()
+    }
+
+    /// Minions that can act on points of interest, with where they are, in
+    /// the order of their ids.
+    pub fn actuators(&self) -> Vec<(PeerId, Coordinate)> {
+        let mut actuators = self
+            .minions
+            .iter()
+            .filter(|(peer_id, _)| self.capabilities.get(peer_id).is_some_and(|c| c.actuator))
+            .map(|(peer_id, position)| (*peer_id, *position))
+            .collect::<Vec<_>>();
+        actuators.sort_by_key(|(peer_id, _)| *peer_id);
+        actuators
+    }
+
+    /// `minions` as seen by the partitioners of `area`, which starts at `origin`.
+    pub fn participants(&self, minions: &[PeerId], origin: Coordinate, area: &Array2<u32>) -> Vec<Participant> {
+        self.weights(minions, area.len())
+            .into_iter()
+            .zip(minions)
+            .map(|(weight, peer_id)| {
+                let position = self.minions.get(peer_id).copied().unwrap_or(origin);
+                Participant {
+                    position: Coordinate { x: position.x - origin.x, y: position.y - origin.y },
+                    weight,
+                }
+            })
+            .collect()
+    }
+
+    /// Marks a position reported by `peer_id` as searched in the task of that
+    /// minion covering it. Cells it released to another minion stay theirs.
+    pub fn report(&mut self, peer_id: PeerId, position: Coordinate) {
+        for (task_id, progress) in self.tasks.iter_mut() {
+            if progress.peer_id == peer_id && !self.completed.contains(task_id) && progress.mark(position) {
+                break;
+            }
+        }
+    }
+
+    /// Ids and minions of the tasks that are not complete yet.
+    pub fn unfinished(&self) -> Vec<(u64, PeerId)> {
+        self.tasks
+            .iter()
+            .filter(|(id, _)| !self.completed.contains(id))
+            .map(|(id, p)| (*id, p.peer_id))
+            .collect()
+    }
+
+    /// Whether `peer_id` has a task left to search, or is about to be handed
+    /// part of another minion's.
+    pub fn is_busy(&self, peer_id: &PeerId) -> bool {
+        self.unfinished().iter().any(|(_, p)| p == peer_id) || self.stealing.values().any(|p| p == peer_id)
+    }
+
+    /// Withdraws an unfinished task, returning the parts that are still to be
+    /// searched.
+    pub fn withdraw_task(&mut self, task_id: u64) -> Vec<(Coordinate, Array2<u32>)> {
+        if self.completed.contains(&task_id) {
+            return Vec::new();
+        }
+        self.stealing.remove(&task_id);
+        match self.tasks.remove(&task_id) {
+            Some(progress) => {
+                self.total -= 1;
+                progress.remaining()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The unfinished task of another minion with the most cells left to
+    /// search, for `thief` to take half of. Tasks are split one at a time.
+    pub fn steal_target(&self, thief: &PeerId) -> Option<(u64, PeerId)> {
+        self.tasks
+            .iter()
+            .filter(|(id, p)| {
+                p.peer_id != *thief && !self.completed.contains(id) && !self.stealing.contains_key(id)
+            })
+            .map(|(id, p)| (p.remaining_cells(), *id, p.peer_id))
+            .filter(|(cells, _, _)| *cells >= 2)
+            .max()
+            .map(|(_, id, peer_id)| (id, peer_id))
+    }
+
+    /// Takes the cells a minion released from `task_id` out of the task,
+    /// returning them for the minion that stole them.
+    pub fn release(&mut self, task_id: u64, from: Option<Coordinate>) -> Vec<(Coordinate, Array2<u32>)> {
+        self.stealing.remove(&task_id);
+        match (self.tasks.get_mut(&task_id), from) {
+            (Some(progress), Some(from)) => progress.release(from),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete == self.total && self.unassigned.is_empty() && self.stealing.is_empty()
+    }
+}
+
 impl Coordinate {
 
     pub fn manhatten_distance(self, other: Self) -> f64 {
@@ -106,30 +307,34 @@ impl Coordinate {
 }
 
 impl Stream for MinionStream {
-    type Item = MinionHeartbeat;
+    type Item = MinionEvent;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut shared_state = self.shared_state.lock().unwrap();
 
         if shared_state.heartbeat {
+            shared_state.heartbeat = false;
             if shared_state.area_exhausted {
-                shared_state.heartbeat = false;
-                return Poll::Ready(None);
+                return Poll::Ready(Some(MinionEvent::AreaExhausted));
             }
-            shared_state.heartbeat = false;
-            return Poll::Ready(Some(MinionHeartbeat {
-                position: shared_state.local_position.clone(),
-                poi: shared_state.poi.clone(),
-            }));
-        } else {
-            shared_state.waker = Some(cx.waker().clone());
-            return Poll::Pending;
+            if shared_state.in_transit {
+                return Poll::Ready(Some(MinionEvent::Moved(shared_state.local_position)));
+            }
+            return Poll::Ready(Some(MinionEvent::Searched(MinionHeartbeat {
+                position: shared_state.local_position,
+                poi: shared_state.poi,
+            })));
         }
+
+        shared_state.waker = Some(cx.waker().clone());
+        Poll::Pending
     }
 }
 
 impl MinionStream {
-    pub fn new(shared_state: Arc<Mutex<MinionState>>) -> Self {
+    /// Flies to the mission area one cell every `step`, then searches it one
+    /// cell every `interval`.
+    pub fn new(shared_state: Arc<Mutex<MinionState>>, step: Duration, interval: Duration) -> Self {
         let thread_shared_state = shared_state.clone();
         thread::spawn(move || 'outer: loop {
             thread::sleep(Duration::from_millis(100));
@@ -139,25 +344,42 @@ impl MinionStream {
                 drop(shared_state);
 
                 loop {
-                    thread::sleep(Duration::from_millis(100));
+                    let in_transit = !thread_shared_state.lock().unwrap().transit.is_empty();
+                    thread::sleep(if in_transit { step } else { interval });
                     let mut shared_state = thread_shared_state.lock().unwrap();
 
+                    if let Some(position) = shared_state.transit.pop_front() {
+                        shared_state.local_position = position;
+                        shared_state.in_transit = true;
+                        shared_state.heartbeat = true;
+                        if let Some(waker) = shared_state.waker.take() {
+                            waker.wake()
+                        };
+                        continue;
+                    }
+                    shared_state.in_transit = false;
+
                     match &mut shared_state.mission_area {
                         Some(area) => {
                             let current_location = area.next();
                             match current_location {
                                 Some(((x, y), poi)) => {
                                     shared_state.local_position = Coordinate { x, y };
-                                    shared_state.poi = if poi != 0 { true } else { false };
+                                    shared_state.poi = poi != 0;
                                     shared_state.heartbeat = true;
                                     if let Some(waker) = shared_state.waker.take() {
                                         waker.wake()
                                     };
                                 }
                                 None => {
+                                    // wait for the next task
                                     shared_state.heartbeat = true;
                                     shared_state.area_exhausted = true;
-                                    break 'outer;
+                                    shared_state.ready = false;
+                                    if let Some(waker) = shared_state.waker.take() {
+                                        waker.wake()
+                                    };
+                                    continue 'outer;
                                 }
                             }
                         }
@@ -174,7 +396,47 @@ impl MinionStream {
     }
 }
 
+/// Cells of `area` in the order minions search them along `pattern` from
+/// `start`, skipping the cells masked as no-go.
+pub fn search_order(area: &Array2<u32>, pattern: PatternId, start: Coordinate) -> Vec<((i32, i32), u32)> {
+    pattern
+        .pattern()
+        .path(area.dim(), start)
+        .into_iter()
+        .filter(|cell| mask::is_searchable(area[*cell]))
+        .map(|(i, j)| ((i as i32, j as i32), area[[i, j]]))
+        .collect()
+}
+
+// Stream that yields every `period`, used to drive periodic protocol work.
+pub fn ticks(period: Duration) -> Receiver<()> {
+    let (tx, rx) = unbounded();
+    task::spawn(async move {
+        loop {
+            task::sleep(period).await;
+            if tx.send(()).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Splits `area` into strips along its longest axis, one per minion. Strips
+/// of an area without no-go cells are equally wide, the last one taking the
+/// remainder; otherwise they hold similar numbers of searchable cells. Falls
+/// back to fewer strips if the axis is too short for `minion_count`.
 pub fn split_mission_area(area: Array2<u32>, minion_count: usize) -> Vec<([i32; 2], Array2<u32>)> {
+    if minion_count <= 1 {
+        return vec![([0, 0], area)];
+    }
+    if !area.is_empty() && mask::searchable_cells(area.view()) == area.len() {
+        return even_strips(area, minion_count);
+    }
+    partition::weighted_strips(&area, &vec![1.0; minion_count]).into_iter().flatten().collect()
+}
+
+fn even_strips(area: Array2<u32>, minion_count: usize) -> Vec<([i32; 2], Array2<u32>)> {
     let (axis, axis_size) = area
         .shape()
         .iter()
@@ -182,38 +444,15 @@ pub fn split_mission_area(area: Array2<u32>, minion_count: usize) -> Vec<([i32;
         .max_by_key(|(_, v)| *v)
         .unwrap();
 
-    if minion_count > 1 {
-
-        let mut splits = axis_size / minion_count;
-        let rem = axis_size % minion_count;
-
-        if rem > 0 {
-
-            if rem > splits { 
-                splits += 1;
-                let split = area.axis_chunks_iter(Axis(axis), splits);
-                let areas = split.map(|x| x.to_owned()).collect::<Vec<_>>();
-                let x = areas.iter().clone();
-                let x = x.map(|value| value.to_owned());
-                let mut step = splits as i32;
-                let mut origins = vec![[0i32, 0]; x.len()];
-                for i in origins.iter_mut().skip(1) {
-                    i[axis] += step;
-                    step += splits as i32;
-                }
-                let y = origins.into_iter().zip(x);
-                return y.collect::<Vec<_>>();
-            };
-
-            let mut split = area.axis_chunks_iter(Axis(axis), splits);
-            let last1 = split.next_back().unwrap(); // `n-1`th element
-            let last2 = split.next_back().unwrap(); // `n-2`th element
-
-            let split = split.map(|x| x.to_owned());
-            let joint = concatenate(Axis(axis), &[last2, last1]).unwrap();
+    let mut splits = axis_size / minion_count;
+    let rem = axis_size % minion_count;
 
-            let areas = split.chain([joint]).collect::<Vec<_>>();
+    if rem > 0 {
 
+        if rem > splits {
+            splits += 1;
+            let split = area.axis_chunks_iter(Axis(axis), splits);
+            let areas = split.map(|x| x.to_owned()).collect::<Vec<_>>();
             let x = areas.iter().clone();
             let x = x.map(|value| value.to_owned());
             let mut step = splits as i32;
@@ -223,27 +462,75 @@ pub fn split_mission_area(area: Array2<u32>, minion_count: usize) -> Vec<([i32;
                 step += splits as i32;
             }
             let y = origins.into_iter().zip(x);
-
             return y.collect::<Vec<_>>();
+        };
 
-        } else {
+        let mut split = area.axis_chunks_iter(Axis(axis), splits);
+        let last1 = split.next_back().unwrap(); // `n-1`th element
+        let last2 = split.next_back().unwrap(); // `n-2`th element
 
-            let areas = area.axis_chunks_iter(Axis(axis), splits);
-            let x = areas.map(|value| value.to_owned());
-            let mut step = splits as i32;
-            let mut origins = vec![[0i32, 0]; x.len()];
-            for i in origins.iter_mut().skip(1) {
-                i[axis] += step;
-                step += splits as i32;
-            }
-            let y = origins.into_iter().zip(x);
-            return y.collect::<Vec<_>>();
+        let split = split.map(|x| x.to_owned());
+        let joint = concatenate(Axis(axis), &[last2, last1]).unwrap();
+
+        let areas = split.chain([joint]).collect::<Vec<_>>();
+
+        let x = areas.iter().clone();
+        let x = x.map(|value| value.to_owned());
+        let mut step = splits as i32;
+        let mut origins = vec![[0i32, 0]; x.len()];
+        for i in origins.iter_mut().skip(1) {
+            i[axis] += step;
+            step += splits as i32;
         }
+        let y = origins.into_iter().zip(x);
+
+        y.collect::<Vec<_>>()
+
     } else {
-        return vec![([0, 0], area)];
+
+        let areas = area.axis_chunks_iter(Axis(axis), splits);
+        let x = areas.map(|value| value.to_owned());
+        let mut step = splits as i32;
+        let mut origins = vec![[0i32, 0]; x.len()];
+        for i in origins.iter_mut().skip(1) {
+            i[axis] += step;
+            step += splits as i32;
+        }
+        let y = origins.into_iter().zip(x);
+        y.collect::<Vec<_>>()
     }
 }
 
+/// Splits `area` into a grid of `rows x cols` tiles, one per minion. The
+/// factorization of `minion_count` is chosen to minimize the total length of
+/// the cuts, which also keeps tiles close to square, e.g. 6 minions over a
+/// 16x24 grid get 8x8 tiles rather than 16x4 strips. Falls back to fewer tiles
+/// if the grid is too small for `minion_count`.
+pub fn tile_mission_area(area: Array2<u32>, minion_count: usize) -> Vec<([i32; 2], Array2<u32>)> {
+    let (rows, cols) = match tiling(area.dim(), minion_count) {
+        Some(tiling) => tiling,
+        None => return vec![([0, 0], area)],
+    };
+
+    let minions = vec![Participant::new(Coordinate { x: 0, y: 0 }); rows * cols];
+    partition::Tiles.partition(&area, &minions).into_iter().flatten().collect()
+}
+
+// Grid of rows x cols tiles for at most `minion_count` minions with the
+// shortest boundaries, preferring square tiles. None when there are no minions.
+pub(crate) fn tiling((height, width): (usize, usize), minion_count: usize) -> Option<(usize, usize)> {
+    (1..=minion_count.min(height * width)).rev().find_map(|count| {
+        (1..=count)
+            .filter(|rows| count % rows == 0)
+            .map(|rows| (rows, count / rows))
+            .filter(|(rows, cols)| *rows <= height && *cols <= width)
+            .min_by_key(|(rows, cols)| {
+                let (h, w) = (height / rows, width / cols);
+                (rows * width + cols * height, h.max(w) - h.min(w))
+            })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -287,6 +574,123 @@ mod tests {
         assert!(dif_6.abs() < epsilon);
     }
 
+    #[test]
+    fn withdraw_tasks() {
+        let (failed, survivor) = (PeerId::random(), PeerId::random());
+        let mut delegate_tasks = DelegateTasks::default();
+        for (task_id, peer_id, x) in [(1, failed, 0), (2, survivor, 2)] {
+            delegate_tasks.assign(&DelegateTaskMessage {
+                task_id,
+                peer_id,
+                global_coordinates: Coordinate { x, y: 0 },
+                area: Array2::<u32>::zeros((2, 3)),
+                pattern: PatternId::default(),
+                start: Coordinate { x: 0, y: 0 },
+            });
+        }
+        delegate_tasks.report(failed, Coordinate { x: 0, y: 0 });
+        delegate_tasks.report(failed, Coordinate { x: 0, y: 1 });
+        delegate_tasks.report(failed, Coordinate { x: 0, y: 2 });
+        assert!(delegate_tasks.complete_task(2));
+        assert!(!delegate_tasks.complete_task(2));
+
+        assert_eq!(delegate_tasks.unfinished(), vec![(1, failed)]);
+        let remaining = delegate_tasks.withdraw_task(1);
+        assert_eq!(remaining, vec![(Coordinate { x: 1, y: 0 }, Array2::<u32>::zeros((1, 3)))]);
+        assert!(delegate_tasks.is_complete());
+        // a late completion of the withdrawn task is not counted
+        assert!(!delegate_tasks.complete_task(1));
+        assert!(delegate_tasks.withdraw_task(2).is_empty());
+
+        delegate_tasks.unassigned = remaining;
+        assert!(!delegate_tasks.is_complete());
+    }
+
+    #[test]
+    fn steal_from_busiest_minion() {
+        let (idle, busy, nearly_done) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let mut delegate_tasks = DelegateTasks::default();
+        for (task_id, peer_id, x) in [(1, busy, 0), (2, nearly_done, 4)] {
+            delegate_tasks.assign(&DelegateTaskMessage {
+                task_id,
+                peer_id,
+                global_coordinates: Coordinate { x, y: 0 },
+                area: Array2::<u32>::zeros((4, 4)),
+                pattern: PatternId::default(),
+                start: Coordinate { x: 0, y: 0 },
+            });
+        }
+        for x in 4..8 {
+            for y in 0..4 {
+                if (x, y) != (7, 3) {
+                    delegate_tasks.report(nearly_done, Coordinate { x, y });
+                }
+            }
+        }
+
+        assert!(!delegate_tasks.is_busy(&idle) && delegate_tasks.is_busy(&nearly_done));
+        assert_eq!(delegate_tasks.steal_target(&idle), Some((1, busy)));
+        assert_eq!(delegate_tasks.steal_target(&busy), None);
+        delegate_tasks.stealing.insert(1, idle);
+        assert_eq!(delegate_tasks.steal_target(&idle), None);
+        // waiting for the cells it steals, the idle minion is not handed more
+        assert!(delegate_tasks.is_busy(&idle));
+
+        assert!(delegate_tasks.complete_task(1) && delegate_tasks.complete_task(2));
+        // the steal is still pending, so the mission is not complete yet
+        assert!(!delegate_tasks.is_complete());
+        let released = delegate_tasks.release(1, Some(Coordinate { x: 2, y: 0 }));
+        assert_eq!(released, vec![(Coordinate { x: 2, y: 0 }, Array2::<u32>::zeros((2, 4)))]);
+        assert!(delegate_tasks.is_complete());
+    }
+
+    #[test]
+    fn report_after_steal() {
+        let (thief, victim) = (PeerId::random(), PeerId::random());
+        let mut delegate_tasks = DelegateTasks::default();
+        let task = |task_id, peer_id, x, rows| DelegateTaskMessage {
+            task_id,
+            peer_id,
+            global_coordinates: Coordinate { x, y: 0 },
+            area: Array2::<u32>::zeros((rows, 4)),
+            pattern: PatternId::default(),
+            start: Coordinate { x: 0, y: 0 },
+        };
+        delegate_tasks.assign(&task(1, victim, 0, 4));
+        delegate_tasks.stealing.insert(1, thief);
+        let released = delegate_tasks.release(1, Some(Coordinate { x: 2, y: 0 }));
+        assert_eq!(released, vec![(Coordinate { x: 2, y: 0 }, Array2::<u32>::zeros((2, 4)))]);
+        delegate_tasks.assign(&task(2, thief, 2, 2));
+
+        // the victim still searching released cells does not credit the thief
+        delegate_tasks.report(victim, Coordinate { x: 2, y: 0 });
+        delegate_tasks.report(victim, Coordinate { x: 0, y: 0 });
+        delegate_tasks.report(thief, Coordinate { x: 3, y: 1 });
+        delegate_tasks.report(thief, Coordinate { x: 3, y: 1 });
+        let remaining = |delegate_tasks: &DelegateTasks, task_id| delegate_tasks.tasks[&task_id].remaining_cells();
+        assert_eq!((remaining(&delegate_tasks, 1), remaining(&delegate_tasks, 2)), (7, 7));
+    }
+
+    #[test]
+    fn tile_mission_area_test() {
+        let arr = Array2::from_shape_fn((16, 24), |(i, j)| (i * 24 + j) as u32);
+        let tiles = tile_mission_area(arr.clone(), 6);
+        let origins = tiles.iter().map(|(o, _)| *o).collect::<Vec<_>>();
+        assert_eq!(origins, vec![[0, 0], [0, 8], [0, 16], [8, 0], [8, 8], [8, 16]]);
+        assert!(tiles.iter().all(|(_, t)| t.dim() == (8, 8)));
+        assert_eq!(tiles[4].1[[0, 0]], arr[[8, 8]]);
+
+        // uneven sizes differ by at most one cell
+        let tiles = tile_mission_area(Array2::<u32>::zeros((7, 10)), 4);
+        let shapes = tiles.iter().map(|(_, t)| t.dim()).collect::<Vec<_>>();
+        assert_eq!(shapes, vec![(4, 5), (4, 5), (3, 5), (3, 5)]);
+
+        // prime counts fall back to strips, and tiny grids to fewer tiles
+        assert_eq!(tile_mission_area(Array2::<u32>::zeros((5, 20)), 5).len(), 5);
+        assert_eq!(tile_mission_area(Array2::<u32>::zeros((2, 2)), 9).len(), 4);
+        assert_eq!(tile_mission_area(Array2::<u32>::zeros((0, 3)), 2), vec![([0, 0], Array2::zeros((0, 3)))]);
+    }
+
     #[test]
     fn split_mission_area_test() {
         let arr = Array2::<u32>::zeros((12, 4));
@@ -302,4 +706,12 @@ mod tests {
         let splits = split_mission_area(arr, 4);
         assert_eq!(results, splits);
     }
+
+    #[test]
+    fn split_masked_mission_area() {
+        // strips are balanced by searchable cells, the masked top rows count for nothing
+        let arr = mask::apply(Array2::<u32>::zeros((12, 4)), &Array2::from_shape_fn((12, 4), |(i, _)| i < 4));
+        let splits = split_mission_area(arr, 2);
+        assert_eq!(splits.iter().map(|(o, a)| (*o, a.nrows())).collect::<Vec<_>>(), vec![([0, 0], 8), ([8, 0], 4)]);
+    }
 }
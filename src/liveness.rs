@@ -0,0 +1,157 @@
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, Instant};
+
+use crate::D2dError;
+
+/// How often minions publish a heartbeat.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinionStatus {
+    Alive,
+    // Missed heartbeats for `suspect_after`, but may still come back.
+    Suspect,
+    Dead,
+}
+
+#[derive(Debug)]
+struct Liveness {
+    last_seen: Instant,
+    status: MinionStatus,
+}
+
+/// Tracks when each minion was last heard from and classifies it as alive,
+/// suspect or dead.
+#[derive(Debug)]
+pub struct FailureDetector {
+    peers: HashMap<PeerId, Liveness>,
+    pub suspect_after: Duration,
+    pub dead_after: Duration,
+}
+
+impl Default for FailureDetector {
+    fn default() -> Self {
+        FailureDetector::new(Duration::from_secs(3), Duration::from_secs(10))
+    }
+}
+
+impl FailureDetector {
+    pub fn new(suspect_after: Duration, dead_after: Duration) -> Self {
+        FailureDetector {
+            peers: HashMap::new(),
+            suspect_after,
+            dead_after,
+        }
+    }
+
+    /// Timeouts in seconds from `D2D_SUSPECT_AFTER` and `D2D_DEAD_AFTER`,
+    /// falling back to the defaults for unset variables.
+    pub fn from_env() -> Result<Self, D2dError> {
+        let mut detector = FailureDetector::default();
+        if let Some(timeout) = env_secs("D2D_SUSPECT_AFTER")? {
+            detector.suspect_after = timeout;
+        }
+        if let Some(timeout) = env_secs("D2D_DEAD_AFTER")? {
+            detector.dead_after = timeout;
+        }
+        if detector.dead_after < detector.suspect_after {
+            return Err(D2dError::InvalidConfig(
+                "D2D_DEAD_AFTER is shorter than D2D_SUSPECT_AFTER".to_string(),
+            ));
+        }
+        Ok(detector)
+    }
+
+    /// Records a sign of life from `peer_id`.
+    pub fn heartbeat(&mut self, peer_id: PeerId, now: Instant) {
+        self.peers.insert(
+            peer_id,
+            Liveness {
+                last_seen: now,
+                status: MinionStatus::Alive,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    pub fn status(&self, peer_id: &PeerId) -> Option<MinionStatus> {
+        self.peers.get(peer_id).map(|l| l.status)
+    }
+
+    pub fn statuses(&self) -> HashMap<PeerId, MinionStatus> {
+        self.peers.iter().map(|(p, l)| (*p, l.status)).collect()
+    }
+
+    /// Updates the status of every peer, returning those that changed. Call
+    /// this periodically.
+    pub fn check(&mut self, now: Instant) -> Vec<(PeerId, MinionStatus)> {
+        let mut changes = Vec::new();
+        for (peer_id, liveness) in self.peers.iter_mut() {
+            let silence = now.saturating_duration_since(liveness.last_seen);
+            let status = if silence >= self.dead_after {
+                MinionStatus::Dead
+            } else if silence >= self.suspect_after {
+                MinionStatus::Suspect
+            } else {
+                MinionStatus::Alive
+            };
+            if status != liveness.status {
+                liveness.status = status;
+                changes.push((*peer_id, status));
+            }
+        }
+        changes
+    }
+}
+
+fn env_secs(name: &str) -> Result<Option<Duration>, D2dError> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| secs.is_finite() && *secs > 0.0)
+            .map(|secs| Some(Duration::from_secs_f64(secs)))
+            .ok_or_else(|| D2dError::InvalidConfig(format!("{}={:?} is not a timeout", name, value))),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_failures() {
+        let mut detector = FailureDetector::new(Duration::from_secs(3), Duration::from_secs(10));
+        let (alive, flaky, crashed) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let start = Instant::now();
+        for peer_id in [alive, flaky, crashed] {
+            detector.heartbeat(peer_id, start);
+        }
+        assert!(detector.check(start).is_empty());
+
+        let later = start + Duration::from_secs(4);
+        detector.heartbeat(alive, later);
+        let mut changes = detector.check(later);
+        changes.sort_by_key(|(p, _)| *p);
+        let mut expected = vec![(flaky, MinionStatus::Suspect), (crashed, MinionStatus::Suspect)];
+        expected.sort_by_key(|(p, _)| *p);
+        assert_eq!(changes, expected);
+
+        // a suspect that is heard from again recovers
+        let end = start + Duration::from_secs(10);
+        detector.heartbeat(flaky, end - Duration::from_secs(1));
+        detector.heartbeat(alive, end);
+        assert_eq!(detector.check(end), vec![(crashed, MinionStatus::Dead)]);
+        assert_eq!(detector.status(&flaky), Some(MinionStatus::Alive));
+        assert_eq!(detector.statuses().len(), 3);
+
+        detector.remove(&crashed);
+        assert_eq!(detector.status(&crashed), None);
+    }
+}
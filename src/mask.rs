@@ -0,0 +1,48 @@
+use ndarray::{Array2, ArrayView2, Zip};
+
+/// Value of cells that are not to be searched, because they lie outside the
+/// mission polygon or are obstructed. Masked cells travel with the area
+/// through every split, so that no part of the protocol can lose the mask.
+pub const NO_GO: u32 = u32::MAX;
+
+pub fn is_searchable(cell: u32) -> bool {
+    cell != NO_GO
+}
+
+pub fn searchable_cells(area: ArrayView2<u32>) -> usize {
+    area.iter().filter(|c| is_searchable(**c)).count()
+}
+
+/// The mask layer of `area`, `true` for cells that are not to be searched.
+pub fn no_go(area: &Array2<u32>) -> Array2<bool> {
+    area.mapv(|c| !is_searchable(c))
+}
+
+/// Applies a mask layer to `area`, marking the cells set in `no_go` as not to
+/// be searched.
+pub fn apply(mut area: Array2<u32>, no_go: &Array2<bool>) -> Array2<u32> {
+    Zip::from(&mut area).and(no_go).for_each(|cell, &masked| {
+        if masked {
+            *cell = NO_GO;
+        }
+    });
+    area
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompressedGrid;
+
+    #[test]
+    fn mask_round_trip() {
+        let mask = Array2::from_shape_fn((4, 6), |(i, j)| i == j || j == 5);
+        let area = apply(Array2::from_shape_fn((4, 6), |(i, j)| ((i + j) % 2) as u32), &mask);
+        assert_eq!(no_go(&area), mask);
+        assert_eq!(searchable_cells(area.view()), 24 - 8);
+        assert!(!is_searchable(area[[2, 2]]) && is_searchable(area[[2, 3]]));
+
+        // masked cells survive the wire format
+        assert_eq!(CompressedGrid::compress(&area).decompress().unwrap(), area);
+    }
+}
@@ -0,0 +1,249 @@
+use libp2p::PeerId;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::auction::{Announcement, Award, Bid};
+use crate::chunk::Chunk;
+use crate::consensus::Commit;
+use crate::codec::{decode_frame, encode_frame, Codec, Json};
+use crate::version::{ProtocolVersion, Role, PROTOCOL_VERSION};
+use crate::{Capabilities, Coordinate, D2dError, DelegateTaskMessage, MinionHeartbeat, PatternId};
+
+/// Every message exchanged between operator, mothership and minions.
+///
+/// Bincode and MessagePack encode variants by their index, so new variants
+/// are only ever appended; reordering or removing one needs a major version.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum D2dMessage {
+    Hello { version: ProtocolVersion, role: Role },
+    NewMission {
+        #[serde(with = "crate::grid::compressed")]
+        area: Array2<u32>,
+        pattern: PatternId,
+    },
+    Discovery { minions: HashMap<PeerId, Coordinate> },
+    DelegateTask(DelegateTaskMessage),
+    Poi(Coordinate),
+    Report { position: Coordinate, poi: bool },
+    TaskComplete { task_id: u64 },
+    ReportMothership(Coordinate),
+    MissionComplete,
+    Chunk(Chunk),
+    ResendChunks {
+        peer_id: Option<PeerId>,
+        transfer_id: u64,
+        missing: Vec<u32>,
+    },
+    TaskAccepted { task_id: u64 },
+    TaskRejected { task_id: u64, reason: String },
+    Ack { id: u64 },
+    Heartbeat(MinionHeartbeat),
+    CancelTask { task_id: u64 },
+    RequestWork { id: u64 },
+    StealWork { id: u64, task_id: u64 },
+    WorkReleased { id: u64, task_id: u64, from: Option<Coordinate> },
+    Capabilities(Capabilities),
+    AgentTour { agent: PeerId, tour: Vec<Coordinate> },
+    Announce(Announcement),
+    Bid(Bid),
+    Award(Award),
+    Commit(Commit),
+    Done { mission: u64, dead: Vec<PeerId> },
+}
+
+// The major version keeps the name it had in 1.0 envelopes, and the minor
+// version defaults to 0, so that envelopes from any release are recognised.
+#[derive(Serialize)]
+pub(crate) struct Envelope<'a> {
+    version: u16,
+    minor: u16,
+    message: &'a D2dMessage,
+}
+
+// Only the version is read first, so that messages from a newer protocol are
+// reported as such rather than as a parse error.
+#[derive(Deserialize)]
+pub(crate) struct Header {
+    version: u16,
+    #[serde(default)]
+    minor: u16,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct OwnedEnvelope {
+    version: u16,
+    #[serde(default)]
+    minor: u16,
+    message: D2dMessage,
+}
+
+impl<'a> Envelope<'a> {
+    pub(crate) fn new(message: &'a D2dMessage) -> Self {
+        Envelope {
+            version: PROTOCOL_VERSION.major,
+            minor: PROTOCOL_VERSION.minor,
+            message,
+        }
+    }
+}
+
+impl Header {
+    pub(crate) fn check(self) -> Result<(), D2dError> {
+        let version = ProtocolVersion {
+            major: self.version,
+            minor: self.minor,
+        };
+        if !PROTOCOL_VERSION.negotiate(version).is_compatible() {
+            return Err(D2dError::UnsupportedVersion(version));
+        }
+        Ok(())
+    }
+}
+
+impl OwnedEnvelope {
+    pub(crate) fn into_message(self) -> Result<D2dMessage, D2dError> {
+        Header {
+            version: self.version,
+            minor: self.minor,
+        }
+        .check()?;
+        Ok(self.message)
+    }
+}
+
+impl D2dMessage {
+    /// Encodes the message as JSON, the format every node understands.
+    pub fn encode(&self) -> Result<Vec<u8>, D2dError> {
+        self.encode_with(&Json)
+    }
+
+    pub fn encode_with(&self, codec: &dyn Codec) -> Result<Vec<u8>, D2dError> {
+        encode_frame(codec, self)
+    }
+
+    /// Decodes a message produced with any codec.
+    pub fn decode(data: &[u8]) -> Result<Self, D2dError> {
+        decode_frame(data)
+    }
+
+    /// Name of the gossipsub topic the message is published on.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            D2dMessage::Hello { .. } | D2dMessage::Capabilities(_) => "handshake",
+            D2dMessage::NewMission { .. } => "new_mission",
+            D2dMessage::Discovery { .. } => "discovery",
+            D2dMessage::DelegateTask(_)
+            | D2dMessage::TaskAccepted { .. }
+            | D2dMessage::TaskRejected { .. }
+            | D2dMessage::CancelTask { .. }
+            | D2dMessage::RequestWork { .. }
+            | D2dMessage::StealWork { .. }
+            | D2dMessage::WorkReleased { .. } => "delegate_task",
+            D2dMessage::Poi(_) => "poi",
+            D2dMessage::Report { .. } => "reporting",
+            D2dMessage::Heartbeat(_) => "heartbeat",
+            D2dMessage::TaskComplete { .. } | D2dMessage::Ack { .. } => "task_complete",
+            D2dMessage::ReportMothership(_) => "reporting_mothership",
+            D2dMessage::AgentTour { .. } => "acting",
+            D2dMessage::Announce(_) | D2dMessage::Bid(_) | D2dMessage::Award(_) => "auction",
+            D2dMessage::Commit(_) | D2dMessage::Done { .. } => "consensus",
+            D2dMessage::MissionComplete => "mission_complete",
+            D2dMessage::Chunk(_) | D2dMessage::ResendChunks { .. } => "chunk",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auction::Lot;
+    use crate::consensus::Member;
+
+    #[test]
+    fn round_trip() {
+        let messages = vec![
+            D2dMessage::Hello { version: PROTOCOL_VERSION, role: Role::Minion },
+            D2dMessage::Capabilities(Capabilities { speed: 2.5, actuator: true, ..Capabilities::default() }),
+            D2dMessage::NewMission { area: Array2::<u32>::ones((3, 2)), pattern: PatternId::Sector },
+            D2dMessage::Discovery {
+                minions: HashMap::from([(PeerId::random(), Coordinate { x: 1, y: 2 })]),
+            },
+            D2dMessage::DelegateTask(DelegateTaskMessage {
+                task_id: 11,
+                peer_id: PeerId::random(),
+                global_coordinates: Coordinate { x: 4, y: 0 },
+                area: Array2::<u32>::zeros((2, 2)),
+                pattern: PatternId::ExpandingSquare,
+                start: Coordinate { x: -1, y: 3 },
+            }),
+            D2dMessage::TaskAccepted { task_id: 11 },
+            D2dMessage::TaskRejected { task_id: 11, reason: "busy".to_string() },
+            D2dMessage::CancelTask { task_id: 11 },
+            D2dMessage::RequestWork { id: 12 },
+            D2dMessage::StealWork { id: 13, task_id: 11 },
+            D2dMessage::WorkReleased { id: 13, task_id: 11, from: Some(Coordinate { x: 1, y: 0 }) },
+            D2dMessage::Poi(Coordinate { x: -1, y: 7 }),
+            D2dMessage::Report { position: Coordinate { x: 3, y: 3 }, poi: true },
+            D2dMessage::Heartbeat(MinionHeartbeat { position: Coordinate { x: 5, y: 1 }, poi: false }),
+            D2dMessage::TaskComplete { task_id: 11 },
+            D2dMessage::Ack { id: 11 },
+            D2dMessage::ReportMothership(Coordinate { x: 0, y: 9 }),
+            D2dMessage::AgentTour { agent: PeerId::random(), tour: vec![Coordinate { x: 2, y: 2 }, Coordinate { x: 0, y: 1 }] },
+            D2dMessage::MissionComplete,
+            D2dMessage::Announce(Announcement {
+                auction_id: 5,
+                round: 2,
+                lots: vec![(3, Lot::Area { origin: Coordinate { x: 4, y: 0 }, rows: 2, cols: 3, cells: 5 })],
+            }),
+            D2dMessage::Bid(Bid { auction_id: 5, round: 2, costs: vec![(3, 1.5)] }),
+            D2dMessage::Award(Award { auction_id: 5, lot: 3, winner: PeerId::random() }),
+            D2dMessage::Commit(Commit {
+                mission: 11,
+                round: 1,
+                members: vec![Member {
+                    peer_id: PeerId::random(),
+                    position: Coordinate { x: 1, y: 2 },
+                    capabilities: crate::Capabilities::default(),
+                }],
+            }),
+            D2dMessage::Done { mission: 11, dead: vec![PeerId::random()] },
+            D2dMessage::Chunk(crate::chunk::split_frame(3, b"frame", 2).remove(1)),
+            D2dMessage::ResendChunks { peer_id: Some(PeerId::random()), transfer_id: 3, missing: vec![0, 2] },
+        ];
+
+        for message in messages {
+            let encoded = message.encode().unwrap();
+            assert_eq!(D2dMessage::decode(&encoded).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn decode_older_minor_version() {
+        let data = br#"{"version":6,"message":{"Poi":{"x":2,"y":5}}}"#;
+        assert_eq!(
+            D2dMessage::decode(data).unwrap(),
+            D2dMessage::Poi(Coordinate { x: 2, y: 5 })
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert!(matches!(
+            D2dMessage::decode(b"not json"),
+            Err(D2dError::UnknownCodec(b'n'))
+        ));
+        assert!(matches!(
+            D2dMessage::decode(b"{not json"),
+            Err(D2dError::Serialization(_))
+        ));
+        assert!(matches!(
+            D2dMessage::decode(br#"{"version":99,"message":"Unknown"}"#),
+            Err(D2dError::UnsupportedVersion(ProtocolVersion { major: 99, minor: 0 }))
+        ));
+        assert!(matches!(
+            D2dMessage::decode(br#"{"version":6,"message":"Unknown"}"#),
+            Err(D2dError::Serialization(_))
+        ));
+    }
+}
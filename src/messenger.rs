@@ -0,0 +1,99 @@
+use libp2p::gossipsub::{Gossipsub, GossipsubMessage, IdentTopic as Topic};
+use libp2p::PeerId;
+use std::time::Instant;
+
+use crate::chunk::{split_frame, OutgoingTransfers, Reassembler, CHUNK_SIZE, MAX_FRAME_SIZE, MAX_TRANSFER_SIZE};
+use crate::delivery::seed_id;
+use crate::{Codec, D2dError, D2dMessage};
+
+/// Sends and receives protocol messages over gossipsub, splitting frames that
+/// exceed the transport's message size into chunks and reassembling them.
+pub struct Messenger {
+    local_peer_id: PeerId,
+    codec: Box<dyn Codec>,
+    next_transfer_id: u64,
+    outgoing: OutgoingTransfers,
+    incoming: Reassembler,
+}
+
+impl Messenger {
+    pub fn new(local_peer_id: PeerId, codec: Box<dyn Codec>) -> Self {
+        Messenger {
+            local_peer_id,
+            codec,
+            next_transfer_id: seed_id(),
+            outgoing: OutgoingTransfers::new(16),
+            incoming: Reassembler::default(),
+        }
+    }
+
+    pub fn codec(&self) -> &dyn Codec {
+        self.codec.as_ref()
+    }
+
+    /// Encodes `message` and publishes it on its topic, or as chunks if too large.
+    pub fn publish(&mut self, gossipsub: &mut Gossipsub, message: &D2dMessage) -> Result<(), D2dError> {
+        let frame = message.encode_with(self.codec())?;
+        if frame.len() <= MAX_FRAME_SIZE {
+            gossipsub.publish(Topic::new(message.topic()), frame)?;
+            return Ok(());
+        }
+        if frame.len() > MAX_TRANSFER_SIZE {
+            return Err(D2dError::FrameTooLarge(frame.len()));
+        }
+
+        let transfer_id = self.next_transfer_id;
+        self.next_transfer_id = self.next_transfer_id.wrapping_add(1);
+        let chunks = split_frame(transfer_id, &frame, CHUNK_SIZE);
+        self.outgoing.insert(transfer_id, chunks.clone());
+        for chunk in chunks {
+            self.publish_raw(gossipsub, &D2dMessage::Chunk(chunk))?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a received message. Chunks are held back until their frame is
+    /// complete and resend requests are answered, both yielding `None`.
+    pub fn receive(
+        &mut self,
+        gossipsub: &mut Gossipsub,
+        message: &GossipsubMessage,
+    ) -> Result<Option<D2dMessage>, D2dError> {
+        match D2dMessage::decode(&message.data)? {
+            D2dMessage::Chunk(chunk) => {
+                match self.incoming.insert(message.source, chunk, Instant::now())? {
+                    Some(frame) => Ok(Some(D2dMessage::decode(&frame)?)),
+                    None => Ok(None),
+                }
+            }
+            D2dMessage::ResendChunks { peer_id, transfer_id, missing } => {
+                if peer_id == Some(self.local_peer_id) {
+                    for chunk in self.outgoing.resend(transfer_id, &missing) {
+                        self.publish_raw(gossipsub, &D2dMessage::Chunk(chunk))?;
+                    }
+                }
+                Ok(None)
+            }
+            other => Ok(Some(other)),
+        }
+    }
+
+    /// Requests the missing chunks of stalled transfers. Call this periodically.
+    pub fn request_missing(&mut self, gossipsub: &mut Gossipsub) -> Result<(), D2dError> {
+        for request in self.incoming.stalled(Instant::now()) {
+            let message = D2dMessage::ResendChunks {
+                peer_id: request.peer_id,
+                transfer_id: request.transfer_id,
+                missing: request.missing,
+            };
+            self.publish_raw(gossipsub, &message)?;
+        }
+        Ok(())
+    }
+
+    fn publish_raw(&self, gossipsub: &mut Gossipsub, message: &D2dMessage) -> Result<(), D2dError> {
+        let frame = message.encode_with(self.codec())?;
+        gossipsub.publish(Topic::new(message.topic()), frame)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+
+use crate::mask::NO_GO;
+use crate::{Coordinate, D2dError};
+
+// Largest grid a mission may rasterize to.
+pub(crate) const MAX_CELLS: usize = 1 << 24;
+
+/// Position in world coordinates, in the unit of the mission's resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Region bounded by `exterior`, less the regions bounded by `holes`. Rings
+/// are lists of vertices and are closed implicitly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Polygon {
+    pub exterior: Vec<Point>,
+    #[serde(default)]
+    pub holes: Vec<Vec<Point>>,
+}
+
+/// Search region as drawn by an operator: the union of `polygons`, searched
+/// in square cells `resolution` wide.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MissionDefinition {
+    pub polygons: Vec<Polygon>,
+    pub resolution: f64,
+}
+
+/// Grid of a rasterized mission. Cell `(x, y)` covers the square whose lowest
+/// corner is `origin + (x, y) * resolution`; cells whose centre lies outside
+/// the mission are masked as no-go.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster {
+    pub origin: Point,
+    pub resolution: f64,
+    pub area: Array2<u32>,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Point { x, y }
+    }
+}
+
+impl Polygon {
+    pub fn new(exterior: Vec<Point>) -> Self {
+        Polygon { exterior, holes: Vec::new() }
+    }
+
+    pub fn with_hole(mut self, hole: Vec<Point>) -> Self {
+        self.holes.push(hole);
+        self
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        ring_contains(&self.exterior, point) && !self.holes.iter().any(|hole| ring_contains(hole, point))
+    }
+}
+
+impl MissionDefinition {
+    pub fn new(resolution: f64) -> Self {
+        MissionDefinition { polygons: Vec::new(), resolution }
+    }
+
+    pub fn with_polygon(mut self, polygon: Polygon) -> Self {
+        self.polygons.push(polygon);
+        self
+    }
+
+    /// Mission read from the JSON file named by `D2D_MISSION`, if set.
+    pub fn from_env() -> Result<Option<Self>, D2dError> {
+        let path = match env::var("D2D_MISSION") {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+        let json = fs::read_to_string(&path)
+            .map_err(|e| D2dError::InvalidConfig(format!("cannot read mission {:?}: {}", path, e)))?;
+        let mission = serde_json::from_str(&json)
+            .map_err(|e| D2dError::InvalidConfig(format!("malformed mission {:?}: {}", path, e)))?;
+        Ok(Some(mission))
+    }
+
+    /// Lowest and highest corner of the box around every polygon.
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let mut vertices = self.polygons.iter().flat_map(|p| p.exterior.iter());
+        let first = *vertices.next()?;
+        Some(vertices.fold((first, first), |(low, high), v| {
+            (Point::new(low.x.min(v.x), low.y.min(v.y)), Point::new(high.x.max(v.x), high.y.max(v.y)))
+        }))
+    }
+
+    /// Covers the bounding box of the mission with cells, masking those whose
+    /// centre is outside every polygon or inside a hole.
+    pub fn rasterize(&self) -> Result<Raster, D2dError> {
+        if !(self.resolution.is_finite() && self.resolution > 0.0) {
+            return Err(D2dError::InvalidConfig(format!("resolution {} is not positive", self.resolution)));
+        }
+        let degenerate = self.polygons.iter().any(|p| {
+            p.exterior.len() < 3 || p.holes.iter().any(|h| h.len() < 3)
+        });
+        let finite = self.polygons.iter().all(|p| {
+            p.exterior.iter().chain(p.holes.iter().flatten()).all(|v| v.x.is_finite() && v.y.is_finite())
+        });
+        if degenerate || !finite {
+            return Err(D2dError::InvalidConfig("polygons need at least three finite vertices".to_string()));
+        }
+        let (low, high) = self
+            .bounds()
+            .ok_or_else(|| D2dError::InvalidConfig("mission has no polygons".to_string()))?;
+
+        let cells = |extent: f64| ((extent / self.resolution).ceil() as usize).max(1);
+        let (rows, cols) = (cells(high.x - low.x), cells(high.y - low.y));
+        if rows.saturating_mul(cols) > MAX_CELLS {
+            return Err(D2dError::InvalidConfig(format!("{} x {} cells is too fine a resolution", rows, cols)));
+        }
+        let raster = Raster { origin: low, resolution: self.resolution, area: Array2::zeros((0, 0)) };
+        let area = Array2::from_shape_fn((rows, cols), |(i, j)| {
+            let centre = raster.to_world(Coordinate { x: i as i32, y: j as i32 });
+            if self.polygons.iter().any(|p| p.contains(centre)) {
+                0
+            } else {
+                NO_GO
+            }
+        });
+        Ok(Raster { area, ..raster })
+    }
+}
+
+impl Raster {
+    /// World position of the centre of a cell.
+    pub fn to_world(&self, cell: Coordinate) -> Point {
+        Point::new(
+            self.origin.x + (cell.x as f64 + 0.5) * self.resolution,
+            self.origin.y + (cell.y as f64 + 0.5) * self.resolution,
+        )
+    }
+
+    /// Cell holding a world position, if it is on the grid.
+    pub fn to_cell(&self, point: Point) -> Option<Coordinate> {
+        let row = ((point.x - self.origin.x) / self.resolution).floor();
+        let col = ((point.y - self.origin.y) / self.resolution).floor();
+        let (rows, cols) = self.area.dim();
+        if row < 0.0 || col < 0.0 || row >= rows as f64 || col >= cols as f64 {
+            return None;
+        }
+        Some(Coordinate { x: row as i32, y: col as i32 })
+    }
+}
+
+// Even-odd rule: a ray from `point` crosses the ring an odd number of times
+// if the point is inside.
+fn ring_contains(ring: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    for (i, a) in ring.iter().enumerate() {
+        let b = ring[(i + 1) % ring.len()];
+        if (a.y > point.y) != (b.y > point.y) {
+            let crossing = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if point.x < crossing {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mask;
+
+    fn rectangle(x0: f64, y0: f64, x1: f64, y1: f64) -> Vec<Point> {
+        vec![Point::new(x0, y0), Point::new(x1, y0), Point::new(x1, y1), Point::new(x0, y1)]
+    }
+
+    #[test]
+    fn rasterizes_polygons_with_holes() {
+        // a 10 x 10 square with a 4 x 4 lake, and a triangle off to the side
+        // whose hypotenuse runs through the centres of two cells
+        let mission = MissionDefinition::new(2.0)
+            .with_polygon(Polygon::new(rectangle(0.0, 0.0, 10.0, 10.0)).with_hole(rectangle(4.0, 4.0, 8.0, 8.0)))
+            .with_polygon(Polygon::new(vec![Point::new(12.0, 0.0), Point::new(16.0, 0.0), Point::new(12.0, 4.0)]));
+        let raster = mission.rasterize().unwrap();
+
+        assert_eq!(raster.area.dim(), (8, 5));
+        assert_eq!(raster.origin, Point::new(0.0, 0.0));
+        let searchable = raster.area.mapv(|c| u8::from(mask::is_searchable(c)));
+        let expected = ndarray::arr2(&[
+            [1, 1, 1, 1, 1],
+            [1, 1, 1, 1, 1],
+            [1, 1, 0, 0, 1],
+            [1, 1, 0, 0, 1],
+            [1, 1, 1, 1, 1],
+            [0, 0, 0, 0, 0],
+            [1, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0],
+        ]);
+        assert_eq!(searchable, expected);
+
+        assert_eq!(raster.to_world(Coordinate { x: 2, y: 3 }), Point::new(5.0, 7.0));
+        assert_eq!(raster.to_cell(Point::new(5.9, 7.1)), Some(Coordinate { x: 2, y: 3 }));
+        assert_eq!(raster.to_cell(Point::new(-0.1, 1.0)), None);
+        assert_eq!(raster.to_cell(Point::new(1.0, 10.0)), None);
+    }
+
+    #[test]
+    fn rejects_invalid_missions() {
+        assert!(MissionDefinition::new(1.0).rasterize().is_err());
+        let square = Polygon::new(rectangle(0.0, 0.0, 1.0, 1.0));
+        assert!(MissionDefinition::new(0.0).with_polygon(square.clone()).rasterize().is_err());
+        let line = Polygon::new(vec![Point::new(0.0, 0.0), Point::new(1.0, 1.0)]);
+        assert!(MissionDefinition::new(1.0).with_polygon(line).rasterize().is_err());
+        assert!(MissionDefinition::new(1e-6).with_polygon(square).rasterize().is_err());
+
+        let json = r#"{"polygons":[{"exterior":[{"x":0,"y":0},{"x":3,"y":0},{"x":0,"y":3}]}],"resolution":1}"#;
+        let mission: MissionDefinition = serde_json::from_str(json).unwrap();
+        assert_eq!(mask::searchable_cells(mission.rasterize().unwrap().area.view()), 3);
+    }
+}
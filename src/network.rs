@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::gossipsub::{Gossipsub, GossipsubEvent};
+use libp2p::request_response::{
+    ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+    RequestResponseEvent,
+};
+use libp2p::NetworkBehaviour;
+use std::io;
+use std::iter;
+
+use crate::{CodecId, D2dMessage};
+
+/// Largest frame accepted on the task channel. Streams are not bound by the
+/// gossipsub message size, so whole mission areas fit in a single request.
+pub const MAX_TASK_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Gossipsub for broadcasts plus a point-to-point channel for task assignment
+/// and completion, where every request is answered.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "D2dEvent", event_process = false)]
+pub struct D2dBehaviour {
+    pub gossipsub: Gossipsub,
+    pub tasks: RequestResponse<TaskCodec>,
+}
+
+#[derive(Debug)]
+pub enum D2dEvent {
+    Gossipsub(GossipsubEvent),
+    Tasks(RequestResponseEvent<D2dMessage, D2dMessage>),
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskProtocol;
+
+// Requests and responses are framed like any other message, so they share
+// the versioning and codec selection of the gossipsub messages.
+#[derive(Debug, Clone)]
+pub struct TaskCodec {
+    pub codec: CodecId,
+}
+
+impl D2dBehaviour {
+    pub fn new(gossipsub: Gossipsub, codec: CodecId) -> Self {
+        let tasks = RequestResponse::new(
+            TaskCodec { codec },
+            iter::once((TaskProtocol, ProtocolSupport::Full)),
+            RequestResponseConfig::default(),
+        );
+        D2dBehaviour { gossipsub, tasks }
+    }
+}
+
+impl From<GossipsubEvent> for D2dEvent {
+    fn from(event: GossipsubEvent) -> Self {
+        D2dEvent::Gossipsub(event)
+    }
+}
+
+impl From<RequestResponseEvent<D2dMessage, D2dMessage>> for D2dEvent {
+    fn from(event: RequestResponseEvent<D2dMessage, D2dMessage>) -> Self {
+        D2dEvent::Tasks(event)
+    }
+}
+
+impl ProtocolName for TaskProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/d2d/task/6"
+    }
+}
+
+impl TaskCodec {
+    async fn read<T>(&self, io: &mut T) -> io::Result<D2dMessage>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let frame = read_length_prefixed(io, MAX_TASK_FRAME_SIZE).await?;
+        D2dMessage::decode(&frame).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write<T>(&self, io: &mut T, message: D2dMessage) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let frame = message
+            .encode_with(self.codec.codec().as_ref())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        write_length_prefixed(io, frame).await
+    }
+}
+
+#[async_trait]
+impl RequestResponseCodec for TaskCodec {
+    type Protocol = TaskProtocol;
+    type Request = D2dMessage;
+    type Response = D2dMessage;
+
+    async fn read_request<T>(&mut self, _: &TaskProtocol, io: &mut T) -> io::Result<D2dMessage>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        self.read(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &TaskProtocol, io: &mut T) -> io::Result<D2dMessage>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        self.read(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &TaskProtocol,
+        io: &mut T,
+        request: D2dMessage,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        self.write(io, request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &TaskProtocol,
+        io: &mut T,
+        response: D2dMessage,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        self.write(io, response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Coordinate, DelegateTaskMessage, PatternId};
+    use futures::io::Cursor;
+    use libp2p::PeerId;
+    use ndarray::Array2;
+
+    #[test]
+    fn task_frames_round_trip() {
+        let task = D2dMessage::DelegateTask(DelegateTaskMessage {
+            task_id: 1,
+            peer_id: PeerId::random(),
+            global_coordinates: Coordinate { x: 0, y: 8 },
+            area: Array2::<u32>::ones((300, 400)),
+            pattern: PatternId::default(),
+            start: Coordinate { x: 0, y: 0 },
+        });
+        let mut codec = TaskCodec { codec: CodecId::Cbor };
+
+        async_std::task::block_on(async {
+            let mut buffer = Cursor::new(Vec::new());
+            codec.write_request(&TaskProtocol, &mut buffer, task.clone()).await.unwrap();
+            codec
+                .write_response(&TaskProtocol, &mut buffer, D2dMessage::TaskAccepted { task_id: 1 })
+                .await
+                .unwrap();
+
+            buffer.set_position(0);
+            assert_eq!(codec.read_request(&TaskProtocol, &mut buffer).await.unwrap(), task);
+            assert_eq!(
+                codec.read_response(&TaskProtocol, &mut buffer).await.unwrap(),
+                D2dMessage::TaskAccepted { task_id: 1 }
+            );
+        });
+    }
+}
@@ -0,0 +1,483 @@
+use ndarray::{s, Array2, ArrayView2, Axis};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::assignment::hungarian;
+use crate::mask::{is_searchable, searchable_cells};
+use crate::progress::unsearched_rectangles;
+use crate::{config, tiling, Capabilities, Coordinate, D2dError};
+
+/// Part of a mission area assigned to one minion, as rectangles with their
+/// origins in the area.
+pub type Region = Vec<([i32; 2], Array2<u32>)>;
+
+/// A minion taking part in a mission, as seen by the partitioners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Participant {
+    pub position: Coordinate, // Relative to the area.
+    pub weight: f64,          // Share of the area, relative to the other participants.
+}
+
+impl Participant {
+    pub fn new(position: Coordinate) -> Self {
+        Participant { position, weight: 1.0 }
+    }
+}
+
+/// Strategy for dividing a mission area among minions. Shares are measured in
+/// searchable cells, cells masked as no-go count for nothing.
+pub trait AreaPartitioner: Send + Sync {
+    fn id(&self) -> PartitionerId;
+
+    /// Splits `area` into one region per minion, in the order of `minions`,
+    /// sized roughly in proportion to their weights. Every cell ends up in
+    /// exactly one region; regions are empty when the area is too small to
+    /// give every minion a share.
+    fn partition(&self, area: &Array2<u32>, minions: &[Participant]) -> Vec<Region>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PartitionerId {
+    #[default]
+    Strips,
+    Tiles,
+    Bisection,
+    Voronoi,
+}
+
+/// Slices along the longest axis.
+pub struct Strips;
+/// Near-square tiles from a rows x cols grid, with the rows sized by the
+/// weight of their minions.
+pub struct Tiles;
+/// Recursively halves the longest axis, in proportion to the minions on each side.
+pub struct Bisection;
+/// Gives every cell to the nearest minion, with distances scaled down by weight.
+pub struct Voronoi;
+
+impl PartitionerId {
+    /// Partitioner selected through the `D2D_PARTITION` environment variable,
+    /// strips if unset.
+    pub fn from_env() -> Result<Self, D2dError> {
+        config::from_env("D2D_PARTITION")
+    }
+
+    pub fn partitioner(self) -> Box<dyn AreaPartitioner> {
+        match self {
+            PartitionerId::Strips => Box::new(Strips),
+            PartitionerId::Tiles => Box::new(Tiles),
+            PartitionerId::Bisection => Box::new(Bisection),
+            PartitionerId::Voronoi => Box::new(Voronoi),
+        }
+    }
+}
+
+impl FromStr for PartitionerId {
+    type Err = D2dError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strips" => Ok(PartitionerId::Strips),
+            "tiles" => Ok(PartitionerId::Tiles),
+            "bisection" => Ok(PartitionerId::Bisection),
+            "voronoi" => Ok(PartitionerId::Voronoi),
+            _ => Err(D2dError::InvalidConfig(format!("unknown partitioner {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for PartitionerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PartitionerId::Strips => "strips",
+            PartitionerId::Tiles => "tiles",
+            PartitionerId::Bisection => "bisection",
+            PartitionerId::Voronoi => "voronoi",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Hands the regions to the minions so that the time they take between them
+/// is minimal. A minion takes the time to fly from its position to where a
+/// region starts at its speed, then to search the region's cells at its
+/// throughput, so bigger regions go to faster minions unless those start
+/// much further away. `capabilities` are those of `minions`, in their order.
+pub fn assign_regions(regions: Vec<Region>, minions: &[Participant], capabilities: &[Capabilities]) -> Vec<Region> {
+    assert_eq!(regions.len(), minions.len(), "one region per minion");
+    assert_eq!(capabilities.len(), minions.len(), "capabilities for every minion");
+    let cells = regions
+        .iter()
+        .map(|region| region.iter().map(|(_, area)| searchable_cells(area.view())).sum::<usize>())
+        .collect::<Vec<_>>();
+    let cost = Array2::from_shape_fn((minions.len(), regions.len()), |(i, j)| {
+        let distance = regions[j].first().map_or(0.0, |([x, y], _)| {
+            minions[i].position.euclidean_distance(Coordinate { x: *x, y: *y })
+        });
+        distance / capabilities[i].speed + cells[j] as f64 / capabilities[i].throughput()
+    });
+
+    let mut regions = regions.into_iter().map(Some).collect::<Vec<_>>();
+    hungarian(&cost)
+        .into_iter()
+        .map(|j| regions[j].take().unwrap_or_default())
+        .collect()
+}
+
+// Splits the slices of an area, holding `cells` searchable cells each, into
+// ranges whose searchable cells follow `weights`. Equal weights are assumed
+// when none is positive, and raw cells when nothing is searchable.
+pub(crate) fn weighted_ranges(cells: &[usize], weights: &[f64]) -> Vec<(usize, usize)> {
+    let mut prefix = vec![0usize];
+    for count in cells {
+        prefix.push(prefix.last().unwrap() + count);
+    }
+    if prefix[cells.len()] == 0 {
+        prefix = (0..=cells.len()).collect();
+    }
+    let total_cells = prefix[cells.len()] as f64;
+
+    let total = weights.iter().sum::<f64>();
+    let scale = if total > 0.0 { total } else { weights.len() as f64 };
+    let mut cumulative = 0.0;
+    let mut start = 0;
+    weights
+        .iter()
+        .enumerate()
+        .map(|(i, weight)| {
+            cumulative += if total > 0.0 { *weight } else { 1.0 };
+            let end = if i + 1 == weights.len() {
+                cells.len()
+            } else {
+                // the cut closest to the target, the later one on ties
+                let target = total_cells * cumulative / scale;
+                let after = (start..=cells.len()).find(|&e| prefix[e] as f64 >= target).unwrap_or(cells.len());
+                if after > start && target - (prefix[after - 1] as f64) < prefix[after] as f64 - target {
+                    after - 1
+                } else {
+                    after
+                }
+            };
+            let range = (start, end);
+            start = end;
+            range
+        })
+        .collect()
+}
+
+// Searchable cells in every slice of `area` along `axis`.
+fn slice_cells(area: ArrayView2<u32>, axis: usize) -> Vec<usize> {
+    area.axis_iter(Axis(axis)).map(|slice| slice.iter().filter(|c| is_searchable(**c)).count()).collect()
+}
+
+/// Slices `area` along its longest axis by `weights`, leaving out the slices
+/// too thin to hold a cell.
+pub(crate) fn weighted_strips(area: &Array2<u32>, weights: &[f64]) -> Vec<Option<([i32; 2], Array2<u32>)>> {
+    let (rows, cols) = area.dim();
+    let axis = if rows >= cols { 0 } else { 1 };
+    weighted_ranges(&slice_cells(area.view(), axis), weights)
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                None
+            } else if axis == 0 {
+                Some(([start as i32, 0], area.slice(s![start..end, ..]).to_owned()))
+            } else {
+                Some(([0, start as i32], area.slice(s![.., start..end]).to_owned()))
+            }
+        })
+        .collect()
+}
+
+fn weights(minions: &[Participant]) -> Vec<f64> {
+    minions.iter().map(|m| m.weight.max(0.0)).collect()
+}
+
+impl AreaPartitioner for Strips {
+    fn id(&self) -> PartitionerId {
+        PartitionerId::Strips
+    }
+
+    fn partition(&self, area: &Array2<u32>, minions: &[Participant]) -> Vec<Region> {
+        weighted_strips(area, &weights(minions))
+            .into_iter()
+            .map(|strip| strip.into_iter().collect())
+            .collect()
+    }
+}
+
+impl AreaPartitioner for Tiles {
+    fn id(&self) -> PartitionerId {
+        PartitionerId::Tiles
+    }
+
+    fn partition(&self, area: &Array2<u32>, minions: &[Participant]) -> Vec<Region> {
+        let mut regions = vec![Region::new(); minions.len()];
+        let (rows, cols) = match tiling(area.dim(), minions.len()) {
+            Some(tiling) => tiling,
+            None => return regions,
+        };
+
+        // minions fill the grid row by row, those left over get nothing
+        let weights = weights(minions);
+        let row_weights = weights.chunks(cols).take(rows).map(|w| w.iter().sum()).collect::<Vec<f64>>();
+        for (r, (r0, r1)) in weighted_ranges(&slice_cells(area.view(), 0), &row_weights).into_iter().enumerate() {
+            let band = &weights[r * cols..(r + 1) * cols];
+            let band_cells = slice_cells(area.slice(s![r0..r1, ..]), 1);
+            for (c, (c0, c1)) in weighted_ranges(&band_cells, band).into_iter().enumerate() {
+                if r0 < r1 && c0 < c1 {
+                    let tile = area.slice(s![r0..r1, c0..c1]).to_owned();
+                    regions[r * cols + c].push(([r0 as i32, c0 as i32], tile));
+                }
+            }
+        }
+        regions
+    }
+}
+
+impl AreaPartitioner for Bisection {
+    fn id(&self) -> PartitionerId {
+        PartitionerId::Bisection
+    }
+
+    fn partition(&self, area: &Array2<u32>, minions: &[Participant]) -> Vec<Region> {
+        let mut pieces = Vec::new();
+        bisect(area, [0, 0], &weights(minions), &mut pieces);
+        pieces.into_iter().map(|p| p.into_iter().collect()).collect()
+    }
+}
+
+// Appends one entry per weight, holding its rectangle unless the area could
+// not be split any further.
+fn bisect(area: &Array2<u32>, origin: [i32; 2], weights: &[f64], pieces: &mut Vec<Option<([i32; 2], Array2<u32>)>>) {
+    let count = weights.len();
+    if count == 0 {
+        return;
+    }
+    let (rows, cols) = area.dim();
+    let axis = if rows >= cols { 0 } else { 1 };
+    let len = rows.max(cols);
+    if count == 1 || len < 2 {
+        pieces.push(Some((origin, area.clone())));
+        pieces.extend((1..count).map(|_| None));
+        return;
+    }
+
+    let (first, second) = weights.split_at(count / 2);
+    let halves = [first.iter().sum(), second.iter().sum()];
+    let cut = weighted_ranges(&slice_cells(area.view(), axis), &halves)[0].1.clamp(1, len - 1);
+    let (head, tail) = if axis == 0 {
+        (area.slice(s![..cut, ..]), area.slice(s![cut.., ..]))
+    } else {
+        (area.slice(s![.., ..cut]), area.slice(s![.., cut..]))
+    };
+    let mut tail_origin = origin;
+    tail_origin[axis] += cut as i32;
+    bisect(&head.to_owned(), origin, first, pieces);
+    bisect(&tail.to_owned(), tail_origin, second, pieces);
+}
+
+impl AreaPartitioner for Voronoi {
+    fn id(&self) -> PartitionerId {
+        PartitionerId::Voronoi
+    }
+
+    fn partition(&self, area: &Array2<u32>, minions: &[Participant]) -> Vec<Region> {
+        // minions without weight only get cells when nobody else can take them
+        let weights = weights(minions);
+        let candidates = if weights.iter().any(|w| *w > 0.0) {
+            (0..minions.len()).filter(|&m| weights[m] > 0.0).collect::<Vec<_>>()
+        } else {
+            (0..minions.len()).collect()
+        };
+        let nearest = Array2::from_shape_fn(area.dim(), |(i, j)| {
+            candidates
+                .iter()
+                .map(|&m| {
+                    let (dx, dy) = ((i as i32 - minions[m].position.x) as f64, (j as i32 - minions[m].position.y) as f64);
+                    let scale = if weights[m] > 0.0 { weights[m] } else { 1.0 };
+                    (m, (dx * dx + dy * dy).sqrt() / scale)
+                })
+                .fold(None, |best: Option<(usize, f64)>, (m, d)| match best {
+                    Some((_, best_d)) if best_d <= d => best,
+                    _ => Some((m, d)),
+                })
+                .map_or(usize::MAX, |(m, _)| m)
+        });
+
+        (0..minions.len())
+            .map(|m| {
+                let outside = nearest.mapv(|n| n != m);
+                unsearched_rectangles(&outside)
+                    .into_iter()
+                    .map(|([row, col], [rows, cols])| {
+                        let piece = area.slice(s![row..row + rows, col..col + cols]).to_owned();
+                        ([row as i32, col as i32], piece)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mask::{self, NO_GO};
+    use proptest::prelude::*;
+
+    const PARTITIONERS: [PartitionerId; 4] = [
+        PartitionerId::Strips,
+        PartitionerId::Tiles,
+        PartitionerId::Bisection,
+        PartitionerId::Voronoi,
+    ];
+
+    fn mission() -> impl Strategy<Value = (Array2<u32>, Vec<Participant>)> {
+        (1usize..40, 1usize..40, 1usize..12).prop_flat_map(|(rows, cols, count)| {
+            let minions = prop::collection::vec(
+                (-5i32..rows as i32 + 5, -5i32..cols as i32 + 5, 0u8..5).prop_map(|(x, y, weight)| Participant {
+                    position: Coordinate { x, y },
+                    weight: weight as f64,
+                }),
+                count,
+            );
+            // about a fifth of the cells are masked
+            let area = prop::collection::vec(0u32..5, rows * cols).prop_map(move |cells| {
+                Array2::from_shape_fn((rows, cols), |(i, j)| if cells[i * cols + j] == 0 { NO_GO } else { (i * cols + j) as u32 })
+            });
+            (area, minions)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn every_cell_covered_once((area, minions) in mission()) {
+            for id in PARTITIONERS {
+                let regions = id.partitioner().partition(&area, &minions);
+                prop_assert_eq!(regions.len(), minions.len());
+
+                let mut covered = Array2::<u32>::zeros(area.dim());
+                for ([row, col], piece) in regions.iter().flatten() {
+                    let (row, col) = (*row as usize, *col as usize);
+                    let (rows, cols) = piece.dim();
+                    prop_assert_eq!(&area.slice(s![row..row + rows, col..col + cols]), piece);
+                    covered.slice_mut(s![row..row + rows, col..col + cols]).mapv_inplace(|c| c + 1);
+                }
+                prop_assert!(covered.iter().all(|c| *c == 1), "{} covers {:?}", id, covered);
+            }
+        }
+    }
+
+    #[test]
+    fn bisection_balances_cells() {
+        let minions = vec![Participant::new(Coordinate { x: 0, y: 0 }); 3];
+        let regions = Bisection.partition(&Array2::zeros((6, 9)), &minions);
+        let origins = regions.iter().map(|r| r[0].0).collect::<Vec<_>>();
+        assert_eq!(origins, vec![[0, 0], [0, 3], [3, 3]]);
+        assert!(regions.iter().all(|r| r[0].1.len() == 18));
+    }
+
+    #[test]
+    fn weights_size_regions() {
+        let area = Array2::zeros((12, 6));
+        let minions = [3.0, 1.0, 2.0].map(|weight| Participant { position: Coordinate { x: 0, y: 0 }, weight });
+        let cells = |regions: Vec<Region>| {
+            regions.iter().map(|r| r.iter().map(|(_, a)| a.len()).sum::<usize>()).collect::<Vec<_>>()
+        };
+        assert_eq!(cells(Strips.partition(&area, &minions)), vec![36, 12, 24]);
+        assert_eq!(cells(Bisection.partition(&area, &minions)), vec![36, 12, 24]);
+
+        // a single row of tiles, each as wide as its weight
+        let area = Array2::zeros((8, 8));
+        let minions = [1.0, 3.0, 4.0].map(|weight| Participant { position: Coordinate { x: 0, y: 0 }, weight });
+        assert_eq!(cells(Tiles.partition(&area, &minions)), vec![8, 24, 32]);
+
+        // a 2 x 2 grid whose second row is taller, as it holds the heavier minion
+        let minions = [1.0, 1.0, 1.0, 3.0].map(|weight| Participant { position: Coordinate { x: 0, y: 0 }, weight });
+        assert_eq!(cells(Tiles.partition(&area, &minions)), vec![12, 12, 10, 30]);
+    }
+
+    #[test]
+    fn masked_cells_count_for_nothing() {
+        // the left half is obstructed, so the cut moves right
+        let area = mask::apply(Array2::zeros((4, 12)), &Array2::from_shape_fn((4, 12), |(_, j)| j < 6));
+        let minions = [Participant::new(Coordinate { x: 0, y: 0 }); 2];
+        for partitioner in [&Strips as &dyn AreaPartitioner, &Tiles, &Bisection] {
+            let regions = partitioner.partition(&area, &minions);
+            let searchable = regions
+                .iter()
+                .map(|r| r.iter().map(|(_, a)| mask::searchable_cells(a.view())).sum::<usize>())
+                .collect::<Vec<_>>();
+            assert_eq!(searchable, vec![12, 12], "{}", partitioner.id());
+        }
+    }
+
+    #[test]
+    fn voronoi_follows_positions() {
+        let minions = [Coordinate { x: 0, y: 9 }, Coordinate { x: 0, y: 0 }].map(Participant::new);
+        let regions = Voronoi.partition(&Array2::zeros((4, 10)), &minions);
+        assert_eq!(regions[0], vec![([0, 5], Array2::zeros((4, 5)))]);
+        assert_eq!(regions[1], vec![([0, 0], Array2::zeros((4, 5)))]);
+
+        // the heavier minion reaches twice as far
+        let minions = [Participant { weight: 2.0, ..minions[0] }, minions[1]];
+        let regions = Voronoi.partition(&Array2::zeros((1, 10)), &minions);
+        assert_eq!(regions[0], vec![([0, 3], Array2::zeros((1, 7)))]);
+    }
+
+    #[test]
+    fn regions_go_to_the_nearest_minion() {
+        let area = Array2::from_shape_fn((10, 2), |(i, j)| (i * 2 + j) as u32);
+        let minions = [
+            Participant::new(Coordinate { x: 12, y: 0 }),
+            Participant { weight: 2.0, ..Participant::new(Coordinate { x: 0, y: 0 }) },
+            Participant::new(Coordinate { x: -3, y: 1 }),
+        ];
+        let slow = Capabilities::default();
+        let capabilities = [slow, Capabilities { speed: 2.0 * slow.speed, ..slow }, slow];
+        let regions = Strips.partition(&area, &minions);
+        let origins = |regions: &[Region]| regions.iter().map(|r| r[0].0).collect::<Vec<_>>();
+        assert_eq!(origins(&regions), vec![[0, 0], [3, 0], [8, 0]]);
+
+        // the outer minions swap, the faster one keeps its bigger strip
+        let assigned = assign_regions(regions, &minions, &capabilities);
+        assert_eq!(origins(&assigned), vec![[8, 0], [3, 0], [0, 0]]);
+        assert_eq!(assigned[1][0].1, area.slice(s![3..8, ..]));
+    }
+
+    #[test]
+    fn regions_swap_across_capabilities() {
+        let area = Array2::zeros((12, 2));
+        let minion = |x, weight| Participant { weight, ..Participant::new(Coordinate { x, y: 0 }) };
+        let slow = Capabilities::default();
+        let fast = |factor: f64| Capabilities { speed: factor * slow.speed, ..slow };
+        let origins = |regions: &[Region]| regions.iter().map(|r| r[0].0).collect::<Vec<_>>();
+
+        // a slightly faster minion gets as many cells, so the two swap
+        let minions = [minion(11, 1.0), minion(0, 1.05)];
+        let regions = Strips.partition(&area, &minions);
+        assert_eq!(origins(&regions), vec![[0, 0], [6, 0]]);
+        assert_eq!(origins(&assign_regions(regions, &minions, &[slow, fast(1.05)])), vec![[6, 0], [0, 0]]);
+
+        // a much faster minion keeps its bigger strip, though both start next to the smaller one
+        let minions = [minion(11, 3.0), minion(11, 1.0)];
+        let regions = Strips.partition(&area, &minions);
+        assert_eq!(origins(&regions), vec![[0, 0], [9, 0]]);
+        assert_eq!(origins(&assign_regions(regions, &minions, &[fast(3.0), slow])), vec![[0, 0], [9, 0]]);
+
+        // unless flying across the area costs it more time than the slower
+        // minion takes over the extra cells
+        let minions = [minion(11, 3.0), minion(0, 1.0)];
+        let regions = Strips.partition(&area, &minions);
+        assert_eq!(origins(&assign_regions(regions, &minions, &[fast(3.0), slow])), vec![[9, 0], [0, 0]]);
+    }
+
+    #[test]
+    fn partitioner_names() {
+        config::assert_names(PARTITIONERS, "hexagons");
+        for id in PARTITIONERS {
+            assert_eq!(id.partitioner().id(), id);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::coverage::Sweep;
+use crate::{config, Coordinate, D2dError};
+
+/// Order in which a minion searches the cells of its area.
+pub trait SearchPattern: Send + Sync {
+    fn id(&self) -> PatternId;
+
+    /// Every cell of a `rows x cols` area exactly once, in the order they are
+    /// searched by a minion starting out from `start`, relative to the area.
+    fn path(&self, dim: (usize, usize), start: Coordinate) -> Vec<(usize, usize)>;
+}
+
+/// Search pattern of a mission, sent along with every task so that minion and
+/// mothership agree on the order cells are searched and released in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PatternId {
+    #[default]
+    Sweep,
+    Spiral,
+    ExpandingSquare,
+    Sector,
+}
+
+/// Lawnmower sweep with lanes along the longest side, from the nearest corner.
+pub struct Boustrophedon;
+/// Spirals inwards from the nearest corner.
+pub struct SpiralInward;
+/// Spirals outwards from the starting position in growing squares, for
+/// searches around a last known position.
+pub struct ExpandingSquare;
+/// Visits the area in wedges around the starting position, flying out
+/// through one and back through the next.
+pub struct SectorSearch;
+
+// Wedges of a sector search.
+const SECTORS: usize = 6;
+
+impl PatternId {
+    /// Pattern selected through the `D2D_PATTERN` environment variable,
+    /// sweeps if unset.
+    pub fn from_env() -> Result<Self, D2dError> {
+        config::from_env("D2D_PATTERN")
+    }
+
+    pub fn pattern(self) -> Box<dyn SearchPattern> {
+        match self {
+            PatternId::Sweep => Box::new(Boustrophedon),
+            PatternId::Spiral => Box::new(SpiralInward),
+            PatternId::ExpandingSquare => Box::new(ExpandingSquare),
+            PatternId::Sector => Box::new(SectorSearch),
+        }
+    }
+}
+
+impl FromStr for PatternId {
+    type Err = D2dError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sweep" => Ok(PatternId::Sweep),
+            "spiral" => Ok(PatternId::Spiral),
+            "expanding-square" => Ok(PatternId::ExpandingSquare),
+            "sector" => Ok(PatternId::Sector),
+            _ => Err(D2dError::InvalidConfig(format!("unknown search pattern {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for PatternId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PatternId::Sweep => "sweep",
+            PatternId::Spiral => "spiral",
+            PatternId::ExpandingSquare => "expanding-square",
+            PatternId::Sector => "sector",
+        };
+        f.write_str(name)
+    }
+}
+
+// The cell of the area closest to `start`.
+fn clamp((rows, cols): (usize, usize), start: Coordinate) -> (i64, i64) {
+    (
+        (start.x as i64).clamp(0, rows as i64 - 1),
+        (start.y as i64).clamp(0, cols as i64 - 1),
+    )
+}
+
+impl SearchPattern for Boustrophedon {
+    fn id(&self) -> PatternId {
+        PatternId::Sweep
+    }
+
+    fn path(&self, dim: (usize, usize), start: Coordinate) -> Vec<(usize, usize)> {
+        Sweep::plan(dim, start).path(dim)
+    }
+}
+
+impl SearchPattern for SpiralInward {
+    fn id(&self) -> PatternId {
+        PatternId::Spiral
+    }
+
+    fn path(&self, (rows, cols): (usize, usize), start: Coordinate) -> Vec<(usize, usize)> {
+        // spiral clockwise from the top left, then mirror it onto the nearest corner
+        let mut path = Vec::with_capacity(rows * cols);
+        let (mut top, mut left) = (0, 0);
+        let (mut bottom, mut right) = (rows, cols);
+        while top < bottom && left < right {
+            path.extend((left..right).map(|j| (top, j)));
+            path.extend((top + 1..bottom).map(|i| (i, right - 1)));
+            if top + 1 < bottom {
+                path.extend((left..right - 1).rev().map(|j| (bottom - 1, j)));
+            }
+            if left + 1 < right {
+                path.extend((top + 1..bottom - 1).rev().map(|i| (i, left)));
+            }
+            top += 1;
+            left += 1;
+            bottom = bottom.saturating_sub(1);
+            right = right.saturating_sub(1);
+        }
+
+        let flip_rows = (start.x as f64) > (rows as f64 - 1.0) / 2.0;
+        let flip_cols = (start.y as f64) > (cols as f64 - 1.0) / 2.0;
+        path.into_iter()
+            .map(|(i, j)| {
+                (
+                    if flip_rows { rows - 1 - i } else { i },
+                    if flip_cols { cols - 1 - j } else { j },
+                )
+            })
+            .collect()
+    }
+}
+
+impl SearchPattern for ExpandingSquare {
+    fn id(&self) -> PatternId {
+        PatternId::ExpandingSquare
+    }
+
+    fn path(&self, (rows, cols): (usize, usize), start: Coordinate) -> Vec<(usize, usize)> {
+        let total = rows * cols;
+        if total == 0 {
+            return Vec::new();
+        }
+        let (mut x, mut y) = clamp((rows, cols), start);
+        let mut path = vec![(x as usize, y as usize)];
+
+        // legs of 1, 1, 2, 2, 3, 3, ... cells, turning right after each, clipped
+        // to the area so that long thin areas do not cost the whole square
+        let directions = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+        let mut leg = 0;
+        while path.len() < total {
+            let (dx, dy) = directions[leg % 4];
+            let length = (leg / 2 + 1) as i64;
+            // the leg runs along `moving` while `fixed` stays the same
+            let (fixed, fixed_len, moving, moving_len, step) =
+                if dx == 0 { (x, rows, y, cols, dy) } else { (y, cols, x, rows, dx) };
+            if (0..fixed_len as i64).contains(&fixed) {
+                let (low, high) = if step > 0 {
+                    (-moving, moving_len as i64 - 1 - moving)
+                } else {
+                    (moving - moving_len as i64 + 1, moving)
+                };
+                for k in low.max(1)..=high.min(length) {
+                    let cell = moving + step * k;
+                    path.push(if dx == 0 { (x as usize, cell as usize) } else { (cell as usize, y as usize) });
+                }
+            }
+            x += dx * length;
+            y += dy * length;
+            leg += 1;
+        }
+        path
+    }
+}
+
+impl SearchPattern for SectorSearch {
+    fn id(&self) -> PatternId {
+        PatternId::Sector
+    }
+
+    fn path(&self, (rows, cols): (usize, usize), start: Coordinate) -> Vec<(usize, usize)> {
+        if rows * cols == 0 {
+            return Vec::new();
+        }
+        let datum = clamp((rows, cols), start);
+        let mut sectors = vec![Vec::new(); SECTORS];
+        let mut path = vec![(datum.0 as usize, datum.1 as usize)];
+
+        for i in 0..rows {
+            for j in 0..cols {
+                let (dx, dy) = (i as i64 - datum.0, j as i64 - datum.1);
+                if (dx, dy) == (0, 0) {
+                    continue;
+                }
+                let angle = (dy as f64).atan2(dx as f64).rem_euclid(TAU);
+                let sector = ((angle / TAU * SECTORS as f64) as usize).min(SECTORS - 1);
+                sectors[sector].push((dx * dx + dy * dy, angle, (i, j)));
+            }
+        }
+        for (n, mut sector) in sectors.into_iter().enumerate() {
+            sector.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+            // out through even sectors, back towards the datum through odd ones
+            if n % 2 == 1 {
+                sector.reverse();
+            }
+            path.extend(sector.into_iter().map(|(_, _, cell)| cell));
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coverage::path_length;
+    use proptest::prelude::*;
+
+    const PATTERNS: [PatternId; 4] = [
+        PatternId::Sweep,
+        PatternId::Spiral,
+        PatternId::ExpandingSquare,
+        PatternId::Sector,
+    ];
+
+    proptest! {
+        #[test]
+        fn every_cell_searched_once(rows in 1usize..25, cols in 1usize..25, x in -5i32..30, y in -5i32..30) {
+            for id in PATTERNS {
+                let mut path = id.pattern().path((rows, cols), Coordinate { x, y });
+                path.sort();
+                let expected = (0..rows).flat_map(|i| (0..cols).map(move |j| (i, j))).collect::<Vec<_>>();
+                prop_assert_eq!(path, expected, "{}", id);
+            }
+        }
+    }
+
+    #[test]
+    fn spirals_inwards_from_the_nearest_corner() {
+        let path = SpiralInward.path((3, 4), Coordinate { x: 0, y: 0 });
+        let expected = [(0, 0), (0, 1), (0, 2), (0, 3), (1, 3), (2, 3), (2, 2), (2, 1), (2, 0), (1, 0), (1, 1), (1, 2)];
+        assert_eq!(path, expected);
+        assert_eq!(path_length(&path), 11.0);
+
+        let path = SpiralInward.path((3, 4), Coordinate { x: 7, y: 7 });
+        assert_eq!((path[0], path[11]), ((2, 3), (1, 1)));
+    }
+
+    #[test]
+    fn expands_around_the_start() {
+        let path = ExpandingSquare.path((5, 5), Coordinate { x: 2, y: 2 });
+        assert_eq!(&path[..9], &[(2, 2), (2, 3), (3, 3), (3, 2), (3, 1), (2, 1), (1, 1), (1, 2), (1, 3)]);
+        // within the area every step is to a neighbouring cell
+        assert_eq!(path_length(&path), 24.0);
+
+        let path = ExpandingSquare.path((2, 6), Coordinate { x: -3, y: 5 });
+        assert_eq!(path[0], (0, 5));
+
+        // legs outside a long thin area are skipped rather than walked
+        let path = ExpandingSquare.path((1, 200_000), Coordinate { x: 0, y: 100_000 });
+        assert_eq!(&path[..4], &[(0, 100_000), (0, 100_001), (0, 99_999), (0, 100_002)]);
+        assert_eq!(path.len(), 200_000);
+    }
+
+    #[test]
+    fn sectors_start_at_the_datum() {
+        let path = SectorSearch.path((7, 7), Coordinate { x: 3, y: 3 });
+        assert_eq!(path[0], (3, 3));
+        // the first sector leads straight out, the second comes back in
+        assert_eq!(path[1], (4, 3));
+        let second = path.iter().position(|&(i, j)| (i, j) == (3, 6) || (i, j) == (6, 6)).unwrap();
+        assert!(second > 1);
+    }
+
+    #[test]
+    fn pattern_names() {
+        config::assert_names(PATTERNS, "zigzag");
+        for id in PATTERNS {
+            assert_eq!(id.pattern().id(), id);
+        }
+    }
+}
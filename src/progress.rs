@@ -0,0 +1,280 @@
+use libp2p::PeerId;
+use ndarray::{s, Array2};
+
+use crate::mask;
+use crate::partition::weighted_strips;
+use crate::{Coordinate, DelegateTaskMessage, PatternId};
+
+/// Cells of a delegated task that have been reported as searched.
+#[derive(Debug)]
+pub struct TaskProgress {
+    pub peer_id: PeerId,
+    pub origin: Coordinate,
+    pub area: Array2<u32>,
+    pub pattern: PatternId,
+    pub start: Coordinate,
+    // Cells searched, handed over to another minion or masked as no-go
+    searched: Array2<bool>,
+}
+
+impl TaskProgress {
+    pub fn new(task: &DelegateTaskMessage) -> Self {
+        TaskProgress {
+            peer_id: task.peer_id,
+            origin: task.global_coordinates,
+            area: task.area.clone(),
+            pattern: task.pattern,
+            start: task.start,
+            // masked cells are never searched, so they count as done
+            searched: mask::no_go(&task.area),
+        }
+    }
+
+    /// Marks a global position as searched, returning `false` if it lies
+    /// outside the task or was already marked, e.g. because it was released.
+    pub fn mark(&mut self, position: Coordinate) -> bool {
+        let (row, col) = (position.x - self.origin.x, position.y - self.origin.y);
+        if row < 0 || col < 0 {
+            return false;
+        }
+        match self.searched.get_mut((row as usize, col as usize)) {
+            Some(cell) if !*cell => {
+                *cell = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn searched(&self) -> usize {
+        self.searched.iter().filter(|s| **s).count()
+    }
+
+    pub fn remaining_cells(&self) -> usize {
+        self.searched.len() - self.searched()
+    }
+
+    /// Hands over the cells from the global position `from` onwards, in the
+    /// order of the task's search pattern, returning them as rectangles.
+    pub fn release(&mut self, from: Coordinate) -> Vec<(Coordinate, Array2<u32>)> {
+        let first = (from.x - self.origin.x, from.y - self.origin.y);
+        let path = self.pattern.pattern().path(self.searched.dim(), self.start);
+        let start = match path.iter().position(|&(i, j)| (i as i32, j as i32) == first) {
+            Some(start) => start,
+            None => return Vec::new(),
+        };
+        let mut kept = Array2::from_elem(self.searched.dim(), true);
+        for cell in &path[start..] {
+            kept[*cell] = self.searched[*cell];
+        }
+
+        unsearched_rectangles(&kept)
+            .into_iter()
+            .map(|([row, col], [rows, cols])| {
+                self.searched.slice_mut(s![row..row + rows, col..col + cols]).fill(true);
+                let origin = self.origin + Coordinate { x: row as i32, y: col as i32 };
+                let area = self.area.slice(s![row..row + rows, col..col + cols]).to_owned();
+                (origin, area)
+            })
+            .collect()
+    }
+
+    /// Unsearched parts of the task as rectangles, with their global origins.
+    pub fn remaining(&self) -> Vec<(Coordinate, Array2<u32>)> {
+        unsearched_rectangles(&self.searched)
+            .into_iter()
+            .map(|([row, col], [rows, cols])| {
+                let origin = self.origin + Coordinate { x: row as i32, y: col as i32 };
+                let area = self.area.slice(s![row..row + rows, col..col + cols]).to_owned();
+                (origin, area)
+            })
+            .collect()
+    }
+}
+
+// Covers the unsearched cells with rectangles, given as origin and shape.
+// Consecutive rows with the same unsearched column ranges share a rectangle,
+// so an area partially searched lane by lane along its rows yields at most two.
+pub(crate) fn unsearched_rectangles(searched: &Array2<bool>) -> Vec<([usize; 2], [usize; 2])> {
+    let mut rectangles = Vec::new();
+    // column range and first row of rectangles still growing downwards
+    let mut open: Vec<((usize, usize), usize)> = Vec::new();
+
+    for (row, cells) in searched.rows().into_iter().enumerate() {
+        let mut runs = Vec::new();
+        let mut start = None;
+        for (col, &done) in cells.iter().enumerate() {
+            match (done, start) {
+                (false, None) => start = Some(col),
+                (true, Some(s)) => {
+                    runs.push((s, col));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            runs.push((s, cells.len()));
+        }
+
+        let mut still_open = Vec::new();
+        for (range, first_row) in open {
+            if runs.contains(&range) {
+                still_open.push((range, first_row));
+            } else {
+                rectangles.push(([first_row, range.0], [row - first_row, range.1 - range.0]));
+            }
+        }
+        for range in runs {
+            if !still_open.iter().any(|(r, _)| *r == range) {
+                still_open.push((range, row));
+            }
+        }
+        open = still_open;
+    }
+
+    let rows = searched.nrows();
+    for (range, first_row) in open {
+        rectangles.push(([first_row, range.0], [rows - first_row, range.1 - range.0]));
+    }
+    rectangles
+}
+
+/// Splits each of the remaining areas across `minions` by their weights,
+/// along its longest axis.
+pub fn reassign(
+    remaining: Vec<(Coordinate, Array2<u32>)>,
+    minions: &[(PeerId, f64)],
+) -> Vec<(PeerId, Coordinate, Array2<u32>)> {
+    let weights = minions.iter().map(|(_, weight)| *weight).collect::<Vec<_>>();
+    let mut assignments = Vec::new();
+
+    for (origin, area) in remaining {
+        for ((peer_id, _), strip) in minions.iter().zip(weighted_strips(&area, &weights)) {
+            if let Some((offset, piece)) = strip {
+                let origin = origin + Coordinate { x: offset[0], y: offset[1] };
+                assignments.push((*peer_id, origin, piece));
+            }
+        }
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_after_partial_search() {
+        let area = Array2::from_shape_fn((4, 5), |(i, j)| (i * 5 + j) as u32);
+        let mut progress = TaskProgress::new(&DelegateTaskMessage {
+            task_id: 1,
+            peer_id: PeerId::random(),
+            global_coordinates: Coordinate { x: 10, y: 20 },
+            area: area.clone(),
+            pattern: PatternId::default(),
+            start: Coordinate { x: 0, y: 0 },
+        });
+        // searched in row-major order up to the middle of the second row
+        for (i, j) in (0..4).flat_map(|i| (0..5).map(move |j| (i, j))).take(7) {
+            assert!(progress.mark(Coordinate { x: 10 + i, y: 20 + j }));
+        }
+        assert!(!progress.mark(Coordinate { x: 9, y: 20 }));
+        assert!(!progress.mark(Coordinate { x: 14, y: 20 }));
+        assert_eq!(progress.searched(), 7);
+
+        let remaining = progress.remaining();
+        assert_eq!(
+            remaining,
+            vec![
+                (Coordinate { x: 11, y: 22 }, area.slice(s![1..2, 2..5]).to_owned()),
+                (Coordinate { x: 12, y: 20 }, area.slice(s![2..4, ..]).to_owned()),
+            ]
+        );
+        let cells = remaining.iter().map(|(_, a)| a.len()).sum::<usize>();
+        assert_eq!(cells, 20 - 7);
+    }
+
+    #[test]
+    fn masked_cells_are_not_remaining() {
+        let area = mask::apply(Array2::zeros((3, 3)), &Array2::from_shape_fn((3, 3), |(i, j)| i == 2 || j == 0));
+        let mut progress = TaskProgress::new(&DelegateTaskMessage {
+            task_id: 1,
+            peer_id: PeerId::random(),
+            global_coordinates: Coordinate { x: 0, y: 0 },
+            area: area.clone(),
+            pattern: PatternId::default(),
+            start: Coordinate { x: 0, y: 0 },
+        });
+        assert_eq!(progress.remaining_cells(), 4);
+        assert_eq!(progress.remaining(), vec![(Coordinate { x: 0, y: 1 }, Array2::zeros((2, 2)))]);
+
+        progress.mark(Coordinate { x: 0, y: 1 });
+        // the second row is searched backwards, so releasing from its end keeps nothing of it
+        assert_eq!(progress.release(Coordinate { x: 1, y: 2 }).len(), 1);
+        assert_eq!(progress.remaining_cells(), 1);
+    }
+
+    #[test]
+    fn release_hands_over_the_tail() {
+        let area = Array2::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as u32);
+        let mut progress = TaskProgress::new(&DelegateTaskMessage {
+            task_id: 1,
+            peer_id: PeerId::random(),
+            global_coordinates: Coordinate { x: 5, y: 5 },
+            area: area.clone(),
+            pattern: PatternId::default(),
+            start: Coordinate { x: 0, y: 0 },
+        });
+        progress.mark(Coordinate { x: 5, y: 5 });
+
+        // the second row is searched from its end, so the cells before (6, 7) come after it
+        let released = progress.release(Coordinate { x: 6, y: 7 });
+        assert_eq!(
+            released,
+            vec![
+                (Coordinate { x: 6, y: 5 }, area.slice(s![1..2, 0..3]).to_owned()),
+                (Coordinate { x: 7, y: 5 }, area.slice(s![2..3, ..]).to_owned()),
+            ]
+        );
+        assert_eq!(progress.remaining_cells(), 4);
+        assert_eq!(progress.remaining().iter().map(|(_, a)| a.len()).sum::<usize>(), 4);
+        assert!(progress.release(Coordinate { x: 0, y: 0 }).is_empty());
+    }
+
+    #[test]
+    fn rectangles_cover_gaps() {
+        let searched = Array2::from_shape_fn((3, 4), |(i, j)| i == 1 && j != 2);
+        let mut rectangles = unsearched_rectangles(&searched);
+        rectangles.sort();
+        assert_eq!(
+            rectangles,
+            vec![([0, 0], [1, 4]), ([1, 2], [1, 1]), ([2, 0], [1, 4])]
+        );
+        assert!(unsearched_rectangles(&Array2::from_elem((2, 2), true)).is_empty());
+    }
+
+    #[test]
+    fn reassigns_across_survivors() {
+        let survivors = [(PeerId::random(), 1.0), (PeerId::random(), 1.0)];
+        let remaining = vec![
+            (Coordinate { x: 1, y: 2 }, Array2::<u32>::ones((1, 3))),
+            (Coordinate { x: 2, y: 0 }, Array2::<u32>::ones((6, 5))),
+        ];
+        let assignments = reassign(remaining.clone(), &survivors);
+        assert_eq!(assignments.len(), 4);
+        assert_eq!(assignments.iter().map(|(_, _, a)| a.len()).sum::<usize>(), 33);
+        for (peer_id, _) in survivors {
+            assert_eq!(assignments.iter().filter(|(p, _, _)| *p == peer_id).count(), 2);
+        }
+        assert_eq!(assignments[2].1, Coordinate { x: 2, y: 0 });
+        assert_eq!(assignments[3].1, Coordinate { x: 5, y: 0 });
+
+        // a minion twice as fast takes twice the rows
+        let weighted = reassign(remaining, &[(survivors[0].0, 2.0), (survivors[1].0, 1.0)]);
+        assert_eq!(weighted[2].2.dim(), (4, 5));
+        assert_eq!(weighted[3], (survivors[1].0, Coordinate { x: 6, y: 0 }, Array2::ones((2, 5))));
+
+        assert!(reassign(vec![(Coordinate { x: 0, y: 0 }, Array2::ones((2, 2)))], &[]).is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use ndarray::Array2;
+
+use crate::assignment::hungarian;
+use crate::{Coordinate, Tour, TourPlanner};
+
+// Lloyd iterations before giving up on the groups settling.
+const MAX_ITERATIONS: usize = 100;
+
+/// Groups `points` around as many centres as there are `seeds`, starting from
+/// the seeds, with Lloyd's algorithm. Returns the group of every point and
+/// the centre of every group; a group nobody joins keeps its seed.
+pub fn kmeans(points: &[Coordinate], seeds: &[Coordinate]) -> (Vec<usize>, Vec<(f64, f64)>) {
+    let mut centres = seeds.iter().map(|s| (s.x as f64, s.y as f64)).collect::<Vec<_>>();
+    let mut groups = vec![usize::MAX; points.len()];
+    if seeds.is_empty() {
+        return (Vec::new(), centres);
+    }
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut moved = false;
+        for (point, group) in points.iter().zip(groups.iter_mut()) {
+            let nearest = (0..centres.len())
+                .min_by(|&a, &b| squared(*point, centres[a]).total_cmp(&squared(*point, centres[b])))
+                .unwrap();
+            moved |= *group != nearest;
+            *group = nearest;
+        }
+        if !moved {
+            break;
+        }
+
+        let mut sums = vec![(0.0, 0.0, 0usize); centres.len()];
+        for (point, &group) in points.iter().zip(&groups) {
+            sums[group].0 += point.x as f64;
+            sums[group].1 += point.y as f64;
+            sums[group].2 += 1;
+        }
+        for (centre, (x, y, count)) in centres.iter_mut().zip(sums) {
+            if count > 0 {
+                *centre = (x / count as f64, y / count as f64);
+            }
+        }
+    }
+    (groups, centres)
+}
+
+/// Splits the points of interest between agents at `agents`: clusters them
+/// around the agents with k-means, gives every agent the cluster closest to
+/// it and plans each agent's tour of its cluster. Returns a tour per agent.
+pub fn route_agents(agents: &[Coordinate], pois: &[Coordinate], planner: &dyn TourPlanner) -> Vec<Tour> {
+    let (groups, centres) = kmeans(pois, agents);
+    let cost = Array2::from_shape_fn((agents.len(), centres.len()), |(agent, group)| {
+        squared(agents[agent], centres[group]).sqrt()
+    });
+    let assigned = hungarian(&cost);
+
+    agents
+        .iter()
+        .zip(assigned)
+        .map(|(agent, group)| {
+            let cluster = pois.iter().zip(&groups).filter(|(_, &g)| g == group).map(|(poi, _)| *poi).collect::<Vec<_>>();
+            planner.plan(*agent, &cluster)
+        })
+        .collect()
+}
+
+fn squared(point: Coordinate, centre: (f64, f64)) -> f64 {
+    let (dx, dy) = (point.x as f64 - centre.0, point.y as f64 - centre.1);
+    dx * dx + dy * dy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tour::Exact;
+    use proptest::prelude::*;
+
+    fn at(x: i32, y: i32) -> Coordinate {
+        Coordinate { x, y }
+    }
+
+    #[test]
+    fn agents_get_the_cluster_nearest_them() {
+        let pois = [at(0, 0), at(1, 1), at(0, 2), at(20, 20), at(21, 19), at(22, 22)];
+        // seeded the wrong way round, the clusters still end up with the nearest agent
+        let agents = [at(25, 25), at(-3, 0)];
+        let tours = route_agents(&agents, &pois, &Exact);
+
+        assert_eq!(tours[0].order.len(), 3);
+        assert!(tours[0].order.iter().all(|poi| poi.x >= 20));
+        assert!(tours[1].order.iter().all(|poi| poi.x <= 1));
+        assert_eq!(tours[1].order[0], at(0, 0));
+
+        let (groups, centres) = kmeans(&pois, &[at(0, 0), at(10, 10)]);
+        assert_eq!(groups, vec![0, 0, 0, 1, 1, 1]);
+        assert_eq!(centres[1], (21.0, 61.0 / 3.0));
+    }
+
+    proptest! {
+        #[test]
+        fn every_poi_routed_once(
+            pois in prop::collection::vec((-30i32..30, -30i32..30), 0..40),
+            agents in prop::collection::vec((-30i32..30, -30i32..30), 1..5),
+        ) {
+            let pois = pois.into_iter().map(|(x, y)| at(x, y)).collect::<Vec<_>>();
+            let agents = agents.into_iter().map(|(x, y)| at(x, y)).collect::<Vec<_>>();
+            let tours = route_agents(&agents, &pois, &Exact);
+            prop_assert_eq!(tours.len(), agents.len());
+
+            let mut routed = tours.into_iter().flat_map(|tour| tour.order).collect::<Vec<_>>();
+            let mut expected = pois;
+            routed.sort_by_key(|c| (c.x, c.y));
+            expected.sort_by_key(|c| (c.x, c.y));
+            prop_assert_eq!(routed, expected);
+        }
+    }
+}
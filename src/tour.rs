@@ -0,0 +1,364 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{config, Coordinate, D2dError};
+
+// Largest number of points of interest solved exactly.
+pub const EXACT_LIMIT: usize = 12;
+
+// Improvements smaller than this are rounding noise.
+const EPSILON: f64 = 1e-9;
+
+/// Order of visiting points of interest from a starting position, without
+/// returning to it.
+pub trait TourPlanner: Send + Sync {
+    fn id(&self) -> TourPlannerId;
+    fn plan(&self, start: Coordinate, pois: &[Coordinate]) -> Tour;
+}
+
+/// Points of interest in the order they are visited, with the distance
+/// travelled from the start to the last of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tour {
+    pub order: Vec<Coordinate>,
+    pub cost: f64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TourPlannerId {
+    NearestNeighbour,
+    TwoOpt,
+    OrOpt,
+    #[default]
+    Exact,
+}
+
+/// Always visits the nearest point of interest next.
+pub struct NearestNeighbour;
+/// Nearest neighbour tour, untangled by reversing stretches of it.
+pub struct TwoOpt;
+/// 2-opt tour, improved further by moving stretches of up to three points
+/// elsewhere in the tour.
+pub struct OrOpt;
+/// Shortest tour for up to `EXACT_LIMIT` points of interest, an Or-opt tour
+/// for more.
+pub struct Exact;
+
+impl TourPlannerId {
+    /// Planner selected through the `D2D_TOUR` environment variable, the
+    /// exact one if unset.
+    pub fn from_env() -> Result<Self, D2dError> {
+        config::from_env("D2D_TOUR")
+    }
+
+    pub fn planner(self) -> Box<dyn TourPlanner> {
+        match self {
+            TourPlannerId::NearestNeighbour => Box::new(NearestNeighbour),
+            TourPlannerId::TwoOpt => Box::new(TwoOpt),
+            TourPlannerId::OrOpt => Box::new(OrOpt),
+            TourPlannerId::Exact => Box::new(Exact),
+        }
+    }
+}
+
+impl FromStr for TourPlannerId {
+    type Err = D2dError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(TourPlannerId::NearestNeighbour),
+            "2-opt" => Ok(TourPlannerId::TwoOpt),
+            "or-opt" => Ok(TourPlannerId::OrOpt),
+            "exact" => Ok(TourPlannerId::Exact),
+            _ => Err(D2dError::InvalidConfig(format!("unknown tour planner {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for TourPlannerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TourPlannerId::NearestNeighbour => "nearest",
+            TourPlannerId::TwoOpt => "2-opt",
+            TourPlannerId::OrOpt => "or-opt",
+            TourPlannerId::Exact => "exact",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Tour {
+    /// Tour visiting `order` from `start`, the way the mothership moves.
+    pub fn new(start: Coordinate, order: Vec<Coordinate>) -> Self {
+        let cost = tour_cost(start, &order);
+        Tour { order, cost }
+    }
+}
+
+pub fn tour_cost(start: Coordinate, order: &[Coordinate]) -> f64 {
+    let mut position = start;
+    let mut cost = 0.0;
+    for &poi in order {
+        cost += position.manhatten_distance(poi);
+        position = poi;
+    }
+    cost
+}
+
+impl TourPlanner for NearestNeighbour {
+    fn id(&self) -> TourPlannerId {
+        TourPlannerId::NearestNeighbour
+    }
+
+    fn plan(&self, start: Coordinate, pois: &[Coordinate]) -> Tour {
+        Tour::new(start, nearest_neighbour(start, pois))
+    }
+}
+
+impl TourPlanner for TwoOpt {
+    fn id(&self) -> TourPlannerId {
+        TourPlannerId::TwoOpt
+    }
+
+    fn plan(&self, start: Coordinate, pois: &[Coordinate]) -> Tour {
+        let mut order = nearest_neighbour(start, pois);
+        two_opt(start, &mut order);
+        Tour::new(start, order)
+    }
+}
+
+impl TourPlanner for OrOpt {
+    fn id(&self) -> TourPlannerId {
+        TourPlannerId::OrOpt
+    }
+
+    fn plan(&self, start: Coordinate, pois: &[Coordinate]) -> Tour {
+        let mut order = nearest_neighbour(start, pois);
+        // each move can open up the other, so alternate until neither helps
+        loop {
+            let untangled = two_opt(start, &mut order);
+            if !or_opt(start, &mut order) && !untangled {
+                break;
+            }
+        }
+        Tour::new(start, order)
+    }
+}
+
+impl TourPlanner for Exact {
+    fn id(&self) -> TourPlannerId {
+        TourPlannerId::Exact
+    }
+
+    fn plan(&self, start: Coordinate, pois: &[Coordinate]) -> Tour {
+        if pois.len() > EXACT_LIMIT {
+            return OrOpt.plan(start, pois);
+        }
+        Tour::new(start, held_karp(start, pois))
+    }
+}
+
+fn nearest_neighbour(start: Coordinate, pois: &[Coordinate]) -> Vec<Coordinate> {
+    let mut left = pois.to_vec();
+    let mut order = Vec::with_capacity(pois.len());
+    let mut position = start;
+    while !left.is_empty() {
+        let (nearest, _) = left
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| position.manhatten_distance(**a).total_cmp(&position.manhatten_distance(**b)))
+            .unwrap();
+        position = left.swap_remove(nearest);
+        order.push(position);
+    }
+    order
+}
+
+// Reverses stretches of the tour for as long as that shortens it, returning
+// whether it did.
+fn two_opt(start: Coordinate, order: &mut [Coordinate]) -> bool {
+    let n = order.len();
+    let at = |order: &[Coordinate], i: usize| if i == 0 { start } else { order[i - 1] };
+    let mut improved = false;
+    let mut improving = true;
+    while improving {
+        improving = false;
+        // stretch i..=j, in positions counted from the start at 0
+        for i in 1..n {
+            for j in i + 1..=n {
+                let (before, first, last) = (at(order, i - 1), at(order, i), at(order, j));
+                let mut delta = before.manhatten_distance(last) - before.manhatten_distance(first);
+                if j < n {
+                    let after = at(order, j + 1);
+                    delta += first.manhatten_distance(after) - last.manhatten_distance(after);
+                }
+                if delta < -EPSILON {
+                    order[i - 1..j].reverse();
+                    improving = true;
+                    improved = true;
+                }
+            }
+        }
+    }
+    improved
+}
+
+// Moves stretches of one to three points to wherever they shorten the tour
+// most, returning whether any was moved.
+fn or_opt(start: Coordinate, order: &mut Vec<Coordinate>) -> bool {
+    let mut improved = false;
+    let mut improving = true;
+    while improving {
+        improving = false;
+        for length in 1..=3.min(order.len()) {
+            let mut i = 0;
+            while i + length <= order.len() {
+                let before = if i == 0 { start } else { order[i - 1] };
+                let (first, last) = (order[i], order[i + length - 1]);
+                let mut gain = before.manhatten_distance(first);
+                if let Some(&after) = order.get(i + length) {
+                    gain += last.manhatten_distance(after) - before.manhatten_distance(after);
+                }
+
+                let mut rest = order.clone();
+                let stretch = rest.drain(i..i + length).collect::<Vec<_>>();
+                // insert after position k of the rest, counted from the start at 0
+                let best = (0..=rest.len())
+                    .filter(|&k| k != i)
+                    .map(|k| {
+                        let from = if k == 0 { start } else { rest[k - 1] };
+                        let mut cost = from.manhatten_distance(first);
+                        if let Some(&to) = rest.get(k) {
+                            cost += last.manhatten_distance(to) - from.manhatten_distance(to);
+                        }
+                        (k, cost)
+                    })
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+
+                match best {
+                    Some((k, cost)) if cost < gain - EPSILON => {
+                        rest.splice(k..k, stretch);
+                        *order = rest;
+                        improving = true;
+                        improved = true;
+                    }
+                    _ => i += 1,
+                }
+            }
+        }
+    }
+    improved
+}
+
+// Dynamic programme over the subsets of points visited, ending at each of them.
+fn held_karp(start: Coordinate, pois: &[Coordinate]) -> Vec<Coordinate> {
+    let n = pois.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let subsets = 1 << n;
+    let mut cost = vec![f64::INFINITY; subsets * n];
+    let mut previous = vec![usize::MAX; subsets * n];
+    for (last, poi) in pois.iter().enumerate() {
+        cost[(1 << last) * n + last] = start.manhatten_distance(*poi);
+    }
+    for visited in 1..subsets {
+        for last in (0..n).filter(|&last| visited & (1 << last) != 0) {
+            let reached = cost[visited * n + last];
+            if reached.is_infinite() {
+                continue;
+            }
+            for next in (0..n).filter(|&next| visited & (1 << next) == 0) {
+                let extended = (visited | (1 << next)) * n + next;
+                let through = reached + pois[last].manhatten_distance(pois[next]);
+                if through < cost[extended] {
+                    cost[extended] = through;
+                    previous[extended] = last;
+                }
+            }
+        }
+    }
+
+    let all = subsets - 1;
+    let mut last = (0..n).min_by(|&a, &b| cost[all * n + a].total_cmp(&cost[all * n + b])).unwrap();
+    let mut visited = all;
+    let mut order = Vec::with_capacity(n);
+    loop {
+        order.push(pois[last]);
+        let before = previous[visited * n + last];
+        visited &= !(1 << last);
+        if before == usize::MAX {
+            break;
+        }
+        last = before;
+    }
+    order.reverse();
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const PLANNERS: [TourPlannerId; 4] = [
+        TourPlannerId::NearestNeighbour,
+        TourPlannerId::TwoOpt,
+        TourPlannerId::OrOpt,
+        TourPlannerId::Exact,
+    ];
+
+    fn coordinates(max: usize) -> impl Strategy<Value = Vec<Coordinate>> {
+        prop::collection::vec((-50i32..50, -50i32..50).prop_map(|(x, y)| Coordinate { x, y }), 0..max)
+    }
+
+    proptest! {
+        #[test]
+        fn improvements_never_lengthen_tours(pois in coordinates(40)) {
+            let start = Coordinate { x: 0, y: 0 };
+            let mut previous = f64::INFINITY;
+            for id in PLANNERS {
+                let tour = id.planner().plan(start, &pois);
+                let mut visited = tour.order.clone();
+                let mut expected = pois.clone();
+                visited.sort_by_key(|c| (c.x, c.y));
+                expected.sort_by_key(|c| (c.x, c.y));
+                prop_assert_eq!(visited, expected);
+                prop_assert!((tour.cost - tour_cost(start, &tour.order)).abs() < EPSILON);
+                prop_assert!(tour.cost <= previous + EPSILON, "{} {} > {}", id, tour.cost, previous);
+                previous = tour.cost;
+            }
+        }
+
+        #[test]
+        fn exact_is_shortest(pois in coordinates(EXACT_LIMIT + 1)) {
+            let start = Coordinate { x: 3, y: -7 };
+            let exact = Exact.plan(start, &pois).cost;
+            for id in PLANNERS {
+                prop_assert!(exact <= id.planner().plan(start, &pois).cost + EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn beats_nearest_neighbour() {
+        // nearest neighbour heads for the point next door and has to double back across the line
+        let start = Coordinate { x: 0, y: 0 };
+        let pois = [1, -2, 5, -10, 21].map(|y| Coordinate { x: 0, y });
+        let greedy = NearestNeighbour.plan(start, &pois);
+        assert_eq!(greedy.cost, 1.0 + 3.0 + 7.0 + 15.0 + 31.0);
+        for id in [TourPlannerId::OrOpt, TourPlannerId::Exact] {
+            let tour = id.planner().plan(start, &pois);
+            assert_eq!(tour.cost, 10.0 + 31.0, "{}", id);
+        }
+        assert_eq!(Exact.plan(start, &[]), Tour { order: vec![], cost: 0.0 });
+    }
+
+    #[test]
+    fn planner_names() {
+        config::assert_names(PLANNERS, "greedy");
+        for id in PLANNERS {
+            assert_eq!(id.planner().id(), id);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use ndarray::Array2;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f64::consts::SQRT_2;
+
+use crate::mask;
+use crate::Coordinate;
+
+/// Where a minion may fly on its way to an area: anywhere but the masked
+/// cells of the areas it knows about.
+#[derive(Debug, Clone)]
+pub struct TransitMap {
+    origin: Coordinate,
+    blocked: Array2<bool>,
+}
+
+/// Cells flown through from one position to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub waypoints: Vec<Coordinate>, // Every cell entered, ending at the destination.
+    pub length: f64,                // From cell centre to cell centre.
+}
+
+// Entry of the open set, ordered so that the heap pops the lowest estimate.
+struct Open {
+    estimate: f64,
+    cell: usize,
+}
+
+impl TransitMap {
+    /// Map with the no-go cells of `area`, whose first cell is at `origin`.
+    pub fn new(origin: Coordinate, area: &Array2<u32>) -> Self {
+        TransitMap { origin, blocked: mask::no_go(area) }
+    }
+
+    /// Map without obstacles.
+    pub fn open() -> Self {
+        TransitMap { origin: Coordinate { x: 0, y: 0 }, blocked: Array2::from_elem((0, 0), false) }
+    }
+
+    pub fn is_blocked(&self, position: Coordinate) -> bool {
+        let (row, col) = (position.x - self.origin.x, position.y - self.origin.y);
+        row >= 0 && col >= 0 && self.blocked.get((row as usize, col as usize)).copied().unwrap_or(false)
+    }
+
+    /// Shortest route from `from` to `to` with A*, moving to any of the eight
+    /// neighbouring cells but never through or diagonally past a blocked cell.
+    /// `None` if `to` is blocked or walled in.
+    pub fn plan(&self, from: Coordinate, to: Coordinate) -> Option<Route> {
+        if self.is_blocked(to) {
+            return None;
+        }
+        if from == to {
+            return Some(Route { waypoints: Vec::new(), length: 0.0 });
+        }
+
+        // The search is bounded by the map, both ends and a ring around them,
+        // which leaves room to fly around any obstacle.
+        let (rows, cols) = self.blocked.dim();
+        let low = Coordinate {
+            x: from.x.min(to.x).min(self.origin.x) - 1,
+            y: from.y.min(to.y).min(self.origin.y) - 1,
+        };
+        let high = Coordinate {
+            x: from.x.max(to.x).max(self.origin.x + rows as i32) + 1,
+            y: from.y.max(to.y).max(self.origin.y + cols as i32) + 1,
+        };
+        let width = (high.y - low.y + 1) as usize;
+        let cells = (high.x - low.x + 1) as usize * width;
+        let index = |c: Coordinate| (c.x - low.x) as usize * width + (c.y - low.y) as usize;
+        let position = |i: usize| Coordinate { x: low.x + (i / width) as i32, y: low.y + (i % width) as i32 };
+        let inside = |c: Coordinate| c.x >= low.x && c.x <= high.x && c.y >= low.y && c.y <= high.y;
+
+        let mut cost = vec![f64::INFINITY; cells];
+        let mut previous = vec![usize::MAX; cells];
+        let mut open = BinaryHeap::new();
+        cost[index(from)] = 0.0;
+        open.push(Open { estimate: octile(from, to), cell: index(from) });
+
+        while let Some(Open { estimate, cell }) = open.pop() {
+            let current = position(cell);
+            if current == to {
+                break;
+            }
+            // stale entry of a cell that has since been reached more cheaply
+            if estimate > cost[cell] + octile(current, to) {
+                continue;
+            }
+            for (dx, dy) in [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)] {
+                let next = Coordinate { x: current.x + dx, y: current.y + dy };
+                if !inside(next) || self.is_blocked(next) {
+                    continue;
+                }
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal
+                    && (self.is_blocked(Coordinate { x: current.x + dx, y: current.y })
+                        || self.is_blocked(Coordinate { x: current.x, y: current.y + dy }))
+                {
+                    continue;
+                }
+                let step = if diagonal { SQRT_2 } else { 1.0 };
+                let n = index(next);
+                if cost[cell] + step < cost[n] {
+                    cost[n] = cost[cell] + step;
+                    previous[n] = cell;
+                    open.push(Open { estimate: cost[n] + octile(next, to), cell: n });
+                }
+            }
+        }
+
+        let goal = index(to);
+        if previous[goal] == usize::MAX {
+            return None;
+        }
+        let mut waypoints = Vec::new();
+        let mut cell = goal;
+        while cell != index(from) {
+            waypoints.push(position(cell));
+            cell = previous[cell];
+        }
+        waypoints.reverse();
+        Some(Route { waypoints, length: cost[goal] })
+    }
+}
+
+// Length of the shortest route without obstacles, never more than the real one.
+fn octile(a: Coordinate, b: Coordinate) -> f64 {
+    let (dx, dy) = ((a.x - b.x).abs() as f64, (a.y - b.y).abs() as f64);
+    dx.max(dy) + (SQRT_2 - 1.0) * dx.min(dy)
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate).then(other.cell.cmp(&self.cell))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NO_GO;
+
+    #[test]
+    fn straight_and_diagonal_routes() {
+        let map = TransitMap::open();
+        let route = map.plan(Coordinate { x: -5, y: -5 }, Coordinate { x: 0, y: 3 }).unwrap();
+        assert_eq!(route.waypoints.len(), 8);
+        assert_eq!(route.waypoints.last(), Some(&Coordinate { x: 0, y: 3 }));
+        assert!((route.length - (3.0 + 5.0 * SQRT_2)).abs() < 1e-9);
+        // every step is to a neighbouring cell
+        let mut previous = Coordinate { x: -5, y: -5 };
+        for waypoint in &route.waypoints {
+            assert_eq!((waypoint.x - previous.x).abs().max((waypoint.y - previous.y).abs()), 1);
+            previous = *waypoint;
+        }
+        assert_eq!(map.plan(previous, previous).unwrap().waypoints, vec![]);
+    }
+
+    #[test]
+    fn routes_around_no_go_cells() {
+        // a wall across the area with a gap at the bottom
+        let mut area = Array2::<u32>::zeros((5, 5));
+        for row in 0..4 {
+            area[[row, 2]] = NO_GO;
+        }
+        let map = TransitMap::new(Coordinate { x: 10, y: 10 }, &area);
+        let route = map.plan(Coordinate { x: 10, y: 10 }, Coordinate { x: 10, y: 14 }).unwrap();
+        assert!(route.waypoints.iter().all(|w| !map.is_blocked(*w)));
+        assert!(route.length > 4.0);
+        // over the top of the wall, outside the area, is as short as through the gap
+        assert!((route.length - (2.0 + 2.0 * SQRT_2)).abs() < 1e-9, "{:?}", route);
+
+        assert!(map.plan(Coordinate { x: 0, y: 0 }, Coordinate { x: 11, y: 12 }).is_none());
+    }
+
+    #[test]
+    fn walled_in_cells_are_unreachable() {
+        let mut area = Array2::<u32>::from_elem((3, 3), NO_GO);
+        area[[1, 1]] = 0;
+        let map = TransitMap::new(Coordinate { x: 0, y: 0 }, &area);
+        assert!(map.plan(Coordinate { x: -2, y: -2 }, Coordinate { x: 1, y: 1 }).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Version spoken by this build. Peers sharing the major version can talk to
+/// each other; a minor version only adds messages, which older peers drop as
+/// unknown.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 6, minor: 2 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    Compatible,
+    Incompatible(ProtocolVersion),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Operator,
+    Mothership,
+    Minion,
+}
+
+// Compatibility of every peer that has announced itself, and the role it
+// announced itself in.
+#[derive(Debug, Default)]
+pub struct PeerVersions {
+    peers: HashMap<PeerId, Compatibility>,
+    roles: HashMap<PeerId, Role>,
+}
+
+impl ProtocolVersion {
+    pub fn negotiate(self, remote: ProtocolVersion) -> Compatibility {
+        if self.major != remote.major {
+            Compatibility::Incompatible(remote)
+        } else {
+            Compatibility::Compatible
+        }
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl Compatibility {
+    pub fn is_compatible(&self) -> bool {
+        !matches!(self, Compatibility::Incompatible(_))
+    }
+}
+
+impl PeerVersions {
+    pub fn record(&mut self, peer_id: PeerId, remote: ProtocolVersion) -> Compatibility {
+        let compatibility = PROTOCOL_VERSION.negotiate(remote);
+        self.peers.insert(peer_id, compatibility);
+        compatibility
+    }
+
+    /// Records the version and role `peer_id` announced in its `Hello`.
+    pub fn hello(&mut self, peer_id: PeerId, remote: ProtocolVersion, role: Role) -> Compatibility {
+        self.roles.insert(peer_id, role);
+        self.record(peer_id, remote)
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<Compatibility> {
+        self.peers.get(peer_id).copied()
+    }
+
+    pub fn is_compatible(&self, peer_id: &PeerId) -> bool {
+        self.get(peer_id).is_some_and(|c| c.is_compatible())
+    }
+
+    /// Whether `peer_id` said hello as a minion speaking a compatible version.
+    pub fn is_minion(&self, peer_id: &PeerId) -> bool {
+        self.is_compatible(peer_id) && self.roles.get(peer_id) == Some(&Role::Minion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compatibility_matrix() {
+        let v = |major, minor| ProtocolVersion { major, minor };
+        let matrix = [
+            (v(1, 1), v(1, 1), Compatibility::Compatible),
+            (v(1, 1), v(1, 2), Compatibility::Compatible),
+            (v(1, 1), v(1, 0), Compatibility::Compatible),
+            (v(1, 0), v(1, 1), Compatibility::Compatible),
+            (v(1, 1), v(2, 0), Compatibility::Incompatible(v(2, 0))),
+            (v(2, 0), v(1, 1), Compatibility::Incompatible(v(1, 1))),
+            (v(1, 1), v(0, 9), Compatibility::Incompatible(v(0, 9))),
+        ];
+
+        for (local, remote, expected) in matrix {
+            assert_eq!(local.negotiate(remote), expected, "{} -> {}", local, remote);
+        }
+    }
+
+    #[test]
+    fn peer_versions() {
+        let mut versions = PeerVersions::default();
+        let old = PeerId::random();
+        let future = PeerId::random();
+        let unknown = PeerId::random();
+
+        versions.record(old, ProtocolVersion { major: PROTOCOL_VERSION.major, minor: 0 });
+        versions.record(future, ProtocolVersion { major: PROTOCOL_VERSION.major + 1, minor: 0 });
+
+        assert!(versions.is_compatible(&old));
+        assert!(!versions.is_compatible(&future));
+        assert!(!versions.is_compatible(&unknown));
+
+        // only peers that said hello as minions are taken for minions
+        let minion = PeerId::random();
+        let operator = PeerId::random();
+        versions.hello(minion, PROTOCOL_VERSION, Role::Minion);
+        versions.hello(operator, PROTOCOL_VERSION, Role::Operator);
+        versions.hello(future, ProtocolVersion { major: PROTOCOL_VERSION.major + 1, minor: 0 }, Role::Minion);
+        assert!(versions.is_minion(&minion));
+        assert!(!versions.is_minion(&operator));
+        assert!(!versions.is_minion(&old));
+        assert!(!versions.is_minion(&future));
+    }
+}